@@ -0,0 +1,334 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::api::{Central as _, CentralEvent, CentralState};
+use futures::StreamExt;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::time::{interval, sleep};
+use tracing::{info, warn};
+
+use crate::error::Result;
+use crate::settings::Settings;
+use crate::state::{adapters, AppState, BleState, RadioWork};
+use crate::{reconnect, registry};
+
+/// How often the adapter list is checked for unplugged or returning radios.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether the radio of the adapter in use is switched on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RadioState {
+    On,
+    /// Scans and reconnect attempts wait until the radio is back on.
+    Off,
+    /// The platform does not tell, the radio is taken to be on.
+    Unknown,
+}
+
+impl From<CentralState> for RadioState {
+    fn from(state: CentralState) -> Self {
+        match state {
+            CentralState::PoweredOn => RadioState::On,
+            CentralState::PoweredOff => RadioState::Off,
+            _ => RadioState::Unknown,
+        }
+    }
+}
+
+/// Published as `adapter-state` when the adapter goes away or its radio is switched.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterState {
+    /// `None` while no adapter is plugged in.
+    pub name: Option<String>,
+    pub radio: RadioState,
+}
+
+impl AdapterState {
+    fn of(ble_state: &BleState) -> Self {
+        Self {
+            name: ble_state.adapter_name.clone(),
+            radio: ble_state.radio,
+        }
+    }
+}
+
+/// Records the radio state of the adapter `name`, pausing or resuming the scan and telling
+/// the frontend on changes.
+///
+/// The lock is only held to record the state, the scan and the lamps are dealt with
+/// once it is released.
+async fn update_radio(
+    app: &AppHandle,
+    state: &AppState,
+    name: &str,
+    radio: RadioState,
+) -> Result<()> {
+    let (work, leds) = {
+        let mut ble_state = state.lock().await?;
+        if ble_state.adapter_name.as_deref() != Some(name) {
+            return Ok(());
+        }
+        let Some(work) = ble_state.set_radio(radio) else {
+            return Ok(());
+        };
+        info!("radio {radio:?}");
+        app.emit("adapter-state", AdapterState::of(&ble_state))?;
+        let leds = ble_state
+            .radio_on()
+            .then(|| ble_state.leds.values().cloned().collect::<Vec<_>>());
+        (work, leds)
+    };
+    if let Some(leds) = leds {
+        reconnect::resume(app, leds).await;
+    }
+
+    // a scan started or dropped with the adapter meanwhile wins over the one dealt with here
+    let current = |ble_state: &BleState| {
+        ble_state.adapter_name.as_deref() == Some(name)
+            && ble_state.scan.is_none()
+            && ble_state.paused_scan.is_none()
+    };
+    match work {
+        RadioWork::Idle => {}
+        RadioWork::Pause(scan) => {
+            let paused = scan.pause().await;
+            let mut ble_state = state.lock().await?;
+            if current(&ble_state) {
+                ble_state.paused_scan = Some(paused);
+            } else {
+                paused.cancel();
+            }
+        }
+        RadioWork::Resume {
+            paused,
+            adapter,
+            previous,
+        } => match paused.resume(adapter).await {
+            Ok(session) => {
+                let stale = {
+                    let mut ble_state = state.lock().await?;
+                    if current(&ble_state) && ble_state.radio_on() {
+                        ble_state.scan = Some(session);
+                        None
+                    } else {
+                        Some(session)
+                    }
+                };
+                if let Some(session) = stale {
+                    session.stop().await;
+                }
+            }
+            Err(e) => {
+                let mut ble_state = state.lock().await?;
+                if current(&ble_state) && ble_state.radio == radio {
+                    ble_state.paused_scan = Some(paused);
+                    ble_state.radio = previous;
+                } else {
+                    paused.cancel();
+                }
+                return Err(e);
+            }
+        },
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_adapter_state(state: State<'_, AppState>) -> Result<AdapterState> {
+    Ok(AdapterState::of(&*state.lock().await?))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterInfo {
+    pub name: String,
+    /// In use right now.
+    pub selected: bool,
+    /// Chosen with `select_adapter`.
+    pub preferred: bool,
+}
+
+#[tauri::command]
+pub async fn list_adapters(app: AppHandle, state: State<'_, AppState>) -> Result<Vec<AdapterInfo>> {
+    let preferred = Settings::load(&app)?.adapter;
+    let (manager, selected) = {
+        let ble_state = state.lock().await?;
+        (ble_state.manager.clone(), ble_state.adapter_name.clone())
+    };
+    Ok(adapters(&manager)
+        .await?
+        .into_iter()
+        .map(|(name, _)| AdapterInfo {
+            selected: selected.as_ref() == Some(&name),
+            preferred: preferred.as_ref() == Some(&name),
+            name,
+        })
+        .collect())
+}
+
+/// Switches to the adapter and remembers it, `None` forgets the choice and keeps the current one.
+///
+/// Lamps connected through the previous adapter are disconnected.
+#[tauri::command]
+pub async fn select_adapter(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: Option<String>,
+) -> Result<()> {
+    if let Some(name) = &name {
+        let manager = state.lock().await?.manager.clone();
+        let (_, adapter) = adapters(&manager)
+            .await?
+            .into_iter()
+            .find(|(item, _)| item == name)
+            .ok_or(anyhow!("adapter {name} not found"))?;
+        let detached = {
+            let mut ble_state = state.lock().await?;
+            if ble_state.adapter_name.as_ref() == Some(name) {
+                None
+            } else {
+                let detached = ble_state.switch_adapter(Some(name.clone()), Some(adapter));
+                app.emit("adapter-changed", name)?;
+                app.emit("adapter-state", AdapterState::of(&ble_state))?;
+                Some(detached)
+            }
+        };
+        if let Some(detached) = detached {
+            detached.stop().await;
+            registry::auto_connect(app.clone());
+        }
+    }
+    Settings::update(&app, |settings| {
+        settings.adapter = name;
+        Ok(())
+    })
+    .await
+}
+
+/// Follows adapters being unplugged and plugged back in, and polls the radio state
+/// for platforms that do not report it as an event.
+///
+/// The preferred adapter is taken whenever it is present, otherwise the current one
+/// is kept, falling back to the first available when it disappears.
+async fn check(app: &AppHandle) -> Result<()> {
+    let Some(state) = app.try_state::<AppState>() else {
+        return Ok(());
+    };
+    let preferred = Settings::load(app)?.adapter;
+    // the adapters are listed and the old lamps torn down without holding the lock
+    let (manager, current) = {
+        let Ok(ble_state) = state.lock().await else {
+            return Ok(());
+        };
+        (ble_state.manager.clone(), ble_state.adapter_name.clone())
+    };
+    let mut adapters = adapters(&manager).await?;
+    let position = |name: Option<&String>| adapters.iter().position(|(item, _)| Some(item) == name);
+    let index = position(preferred.as_ref())
+        .or(position(current.as_ref()))
+        .or((!adapters.is_empty()).then_some(0));
+
+    let (detached, attached, adapter) = {
+        let mut ble_state = state.lock().await?;
+        // switched meanwhile, e.g. by `select_adapter`, the next check picks it up
+        if ble_state.adapter_name != current {
+            return Ok(());
+        }
+        // the registered lamps are looked for again on an adapter switched to
+        let (detached, attached) = match index {
+            Some(index) if current.as_ref() != Some(&adapters[index].0) => {
+                let (name, adapter) = adapters.swap_remove(index);
+                info!("switch to adapter {name}");
+                let detached = ble_state.switch_adapter(Some(name.clone()), Some(adapter));
+                app.emit("adapter-changed", Some(name))?;
+                app.emit("adapter-state", AdapterState::of(&ble_state))?;
+                (Some(detached), true)
+            }
+            None if current.is_some() => {
+                warn!("adapter removed");
+                let detached = ble_state.switch_adapter(None, None);
+                app.emit("adapter-changed", None::<String>)?;
+                app.emit("adapter-state", AdapterState::of(&ble_state))?;
+                (Some(detached), false)
+            }
+            _ => (None, false),
+        };
+        let adapter = ble_state
+            .adapter_name
+            .clone()
+            .map(|name| (name, ble_state.adapter.clone()));
+        (detached, attached, adapter)
+    };
+    if let Some(detached) = detached {
+        detached.stop().await;
+    }
+    if attached {
+        registry::auto_connect(app.clone());
+    }
+
+    let Some((name, adapter)) = adapter else {
+        return Ok(());
+    };
+    if let Ok(radio) = adapter.adapter_state().await {
+        update_radio(app, &state, &name, radio.into()).await?;
+    }
+    Ok(())
+}
+
+/// Follows the radio of the adapter in use until the adapter is replaced.
+async fn listen(app: &AppHandle) -> Result<()> {
+    let Some(state) = app.try_state::<AppState>() else {
+        return Ok(());
+    };
+    let (adapter, name) = {
+        let Ok(ble_state) = state.lock().await else {
+            return Ok(());
+        };
+        (ble_state.adapter.clone(), ble_state.adapter_name.clone())
+    };
+    let Some(name) = name else {
+        return Ok(());
+    };
+    let mut events = adapter.events().await?;
+    let mut check = interval(WATCH_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(CentralEvent::StateUpdate(radio)) => {
+                    update_radio(app, &state, &name, radio.into()).await?;
+                }
+                Some(_) => {}
+                None => return Ok(()),
+            },
+            _ = check.tick() => {
+                if state.lock().await?.adapter_name.as_ref() != Some(&name) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+pub fn watch(app: AppHandle) {
+    tauri::async_runtime::spawn({
+        let app = app.clone();
+        async move {
+            let mut interval = interval(WATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = check(&app).await {
+                    warn!("adapter watch error: {e}");
+                }
+            }
+        }
+    });
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = listen(&app).await {
+                warn!("radio watch error: {e}");
+            }
+            sleep(WATCH_INTERVAL).await;
+        }
+    });
+}
@@ -1,85 +1,147 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
-use btleplug::api::{Central, CentralEvent, Peripheral, PeripheralProperties, ScanFilter};
-use btleplug::platform::PeripheralId;
+use btleplug::api::{Central, Peripheral, PeripheralProperties};
+use btleplug::platform::{Adapter, PeripheralId};
+use chrono::Utc;
 use futures::future::join_all;
-use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tauri::ipc::Channel;
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, State};
+use tokio::sync::Mutex;
 use tracing::info;
-use uuid::uuid;
 
+use crate::color::Color;
 use crate::error::{Error, Result};
-use crate::led::Led;
-use crate::state::{AppState, BleState};
+use crate::led::{CacheInfo, Features, Led, LedCommand};
+use crate::playlist::{Playlist, PlaylistPlayer, PlaylistState};
+use crate::preview::Preview;
+use crate::queue::WriteStatus;
+use crate::registry;
+use crate::scan::{PausedScan, ScanEvent, ScanSession, DEFAULT_SCAN_DURATION};
+use crate::scene::Scene;
+use crate::settings::Settings;
+use crate::state::{get_led, AppState, BleState};
+use crate::timer::{
+    validate, validate_event, TimeTask, TimeTaskDiff, TimeTaskIssue, TimeTaskSync, TimerEvent,
+};
+
+/// Keeps concurrent `init` calls from building two states.
+static INIT_LOCK: Mutex<()> = Mutex::const_new(());
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub id: PeripheralId,
     #[serde(flatten)]
     pub properties: PeripheralProperties,
 }
 
+/// Builds the BLE state, calling it again tears down the connected lamps
+/// and starts over, e.g. after a frontend reload.
 #[tauri::command]
-pub async fn init(app: tauri::AppHandle) -> Result<String> {
-    let ble_state = BleState::new().await?;
+pub async fn init(app: AppHandle, state: State<'_, AppState>) -> Result<String> {
+    let _guard = INIT_LOCK.lock().await;
+    if let Some(old) = state.take().await {
+        info!("re-init, tearing down the previous state");
+        old.shut_down().await;
+    }
+    let ble_state = BleState::new(Settings::load(&app)?.adapter.as_deref()).await?;
     let info = ble_state.adapter.adapter_info().await?;
-    let state = AppState::new(ble_state);
-    app.manage(state);
+    state.set(ble_state).await;
+    registry::auto_connect(app);
     Ok(info)
 }
 
+/// Starts a scan session reporting device deltas, replacing the running one.
+///
+/// The scan stops after `duration` seconds, 30 when not given. While the radio is off
+/// the session starts paused and begins once the radio is back on.
 #[tauri::command]
-pub async fn start_scan(state: State<'_, AppState>, channel: Channel<Value>) -> Result<()> {
-    let ble_state = state.lock().await;
-    let adapter = ble_state.adapter.clone();
-    let mut events = adapter.events().await?;
-    adapter
-        .start_scan(ScanFilter {
-            services: vec![uuid!("e572775c-0df9-4b44-926b-b692e31d6971")],
-            // uuid!("e572775c-0df9-4b44-926b-b692e31d6971")
-        })
-        .await?;
+pub async fn start_scan(
+    state: State<'_, AppState>,
+    channel: Channel<ScanEvent>,
+    duration: Option<u64>,
+) -> Result<()> {
+    let duration = duration.map_or(DEFAULT_SCAN_DURATION, Duration::from_secs);
+    // sessions are only taken out or put back under the lock, they start and stop without it
+    let (previous, adapter) = {
+        let mut ble_state = state.lock().await?;
+        if let Some(paused) = ble_state.paused_scan.take() {
+            paused.cancel();
+        }
+        let adapter = ble_state
+            .radio_on()
+            .then(|| (ble_state.adapter.clone(), ble_state.adapter_name.clone()));
+        (ble_state.scan.take(), adapter)
+    };
+    if let Some(session) = previous {
+        session.stop().await;
+    }
+    let Some((adapter, name)) = adapter else {
+        let stale = state
+            .lock()
+            .await?
+            .paused_scan
+            .replace(PausedScan::new(channel, duration));
+        if let Some(paused) = stale {
+            paused.cancel();
+        }
+        return Ok(());
+    };
 
-    tauri::async_runtime::spawn(async move {
-        while let Some(event) = events.next().await {
-            if matches!(event, CentralEvent::DeviceDiscovered(_)) {
-                let peripherals = adapter.peripherals().await?;
-                let properties_futures = peripherals
-                    .into_iter()
-                    .map(|item| async move { (item.properties().await, item.id()) });
-
-                let properties = join_all(properties_futures).await;
-                let data = properties
-                    .into_iter()
-                    .filter_map(|(properties_result, id)| {
-                        if let Ok(Some(properties)) = properties_result {
-                            Some(Device { id, properties })
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>();
-                channel.send(serde_json::to_value(data)?)?;
+    let session = ScanSession::start(adapter, channel, duration).await?;
+    let (stale, to_pause) = {
+        let mut ble_state = state.lock().await?;
+        if ble_state.adapter_name != name {
+            // the adapter was switched meanwhile
+            (Some(session), None)
+        } else if ble_state.radio_on() {
+            (ble_state.scan.replace(session), None)
+        } else {
+            // the radio went off meanwhile, the scan waits for it like one started while off
+            (None, Some(session))
+        }
+    };
+    if let Some(session) = stale {
+        session.stop().await;
+    }
+    if let Some(session) = to_pause {
+        let paused = session.pause().await;
+        let mut ble_state = state.lock().await?;
+        if ble_state.adapter_name == name && !ble_state.radio_on() {
+            if let Some(stale) = ble_state.paused_scan.replace(paused) {
+                stale.cancel();
             }
+        } else {
+            paused.cancel();
         }
-        Ok::<(), Error>(())
-    });
+    }
     Ok(())
 }
 
 #[tauri::command]
 pub async fn stop_scan(state: State<'_, AppState>) -> Result<()> {
-    let ble_state = state.lock().await;
-    ble_state.adapter.stop_scan().await?;
+    let (paused, session, adapter) = {
+        let mut ble_state = state.lock().await?;
+        let adapter = ble_state.radio_on().then(|| ble_state.adapter.clone());
+        (ble_state.paused_scan.take(), ble_state.scan.take(), adapter)
+    };
+    if let Some(paused) = paused {
+        paused.cancel();
+    }
+    match (session, adapter) {
+        (Some(session), _) => session.stop().await,
+        (None, Some(adapter)) => adapter.stop_scan().await?,
+        (None, None) => {}
+    }
     Ok(())
 }
 
 #[tauri::command]
 pub async fn get_devices(state: State<'_, AppState>) -> Result<Vec<Device>> {
-    let ble_state = state.lock().await;
-    let peripherals = ble_state.adapter.peripherals().await?;
+    let adapter = state.lock().await?.adapter.clone();
+    let peripherals = adapter.peripherals().await?;
     let properties_futures = peripherals
         .into_iter()
         .map(|item| async move { (item.properties().await, item.id()) });
@@ -107,110 +169,371 @@ pub async fn connect(
     #[cfg(dev)]
     info!("connect_device id: {id}");
 
-    let mut ble_state = state.lock().await;
-    let device = if let Some(led) = ble_state.leds.get(&id) {
-        led.peripheral.connect().await?;
-        let device = Device {
-            id: id.clone(),
-            properties: led
-                .peripheral
-                .properties()
-                .await?
-                .ok_or(anyhow!("Device not found"))?,
-        };
-        led.subscribe().await?;
-        device
-    } else {
-        let peripheral = ble_state.adapter.peripheral(&id).await?;
-        let device = Device {
-            id: id.clone(),
-            properties: peripheral
-                .properties()
-                .await?
-                .ok_or(anyhow!("Device not found"))?,
-        };
-        let led = Led::new(peripheral).await?;
-        led.check_connected().await?;
-        led.set_time().await?;
-        led.on_state(app).await?;
-
-        ble_state.leds.insert(id, led);
-        device
+    let device = connect_led(&app, &state, id).await?;
+    registry::touch(&app, &device.id).await?;
+    Ok(device)
+}
+
+/// Connects a lamp, or reconnects it when it has been connected before.
+///
+/// The state is only locked to look the lamp up and to add it, so connecting
+/// never holds up commands sent to other lamps. A lamp being connected for the
+/// first time is marked, a second connect meanwhile fails instead of connecting twice.
+pub async fn connect_led(app: &AppHandle, state: &AppState, id: PeripheralId) -> Result<Device> {
+    let (existing, adapter) = {
+        let mut ble_state = state.lock().await?;
+        let existing = ble_state.leds.get(&id).cloned();
+        if existing.is_none() && !ble_state.connecting.insert(id.clone()) {
+            return Err(anyhow!("led {id} is already being connected").into());
+        }
+        (existing, ble_state.adapter.clone())
     };
+    if let Some(led) = existing {
+        return led
+            .run(|led| async move {
+                led.peripheral.connect().await?;
+                led.invalidate();
+                let device = Device {
+                    id: led.peripheral.id(),
+                    properties: led
+                        .peripheral
+                        .properties()
+                        .await?
+                        .ok_or(anyhow!("Device not found"))?,
+                };
+                led.subscribe().await?;
+                Ok::<_, Error>(device)
+            })
+            .await;
+    }
 
+    let connected = new_led(app, &adapter, &id).await;
+    let mut ble_state = state.lock().await?;
+    ble_state.connecting.remove(&id);
+    let (led, device) = connected?;
+    ble_state.leds.insert(id, led);
     Ok(device)
 }
 
+/// Connects a lamp that has not been connected before and sets it up.
+async fn new_led(app: &AppHandle, adapter: &Adapter, id: &PeripheralId) -> Result<(Led, Device)> {
+    let peripheral = adapter.peripheral(id).await?;
+    let device = Device {
+        id: id.clone(),
+        properties: peripheral
+            .properties()
+            .await?
+            .ok_or(anyhow!("Device not found"))?,
+    };
+    let led = Led::new(peripheral).await?;
+    led.check_connected().await?;
+    led.set_time().await?;
+    led.on_state(app.clone()).await?;
+    Ok((led, device))
+}
+
 #[tauri::command]
-pub async fn control(state: State<'_, AppState>, id: PeripheralId, command: &str) -> Result<()> {
+pub async fn control(
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    command: LedCommand,
+) -> Result<WriteStatus> {
     #[cfg(dev)]
     info!("control id: {id}");
-    let ble_state = state.lock().await;
+    control_led(&state, &id, command).await
+}
+
+pub async fn control_led(
+    state: &AppState,
+    id: &PeripheralId,
+    command: LedCommand,
+) -> Result<WriteStatus> {
+    let led = get_led(state, id).await?;
+    Ok(led
+        .run_latest("control", |led| async move { led.control(command).await })
+        .await?)
+}
+
+#[tauri::command]
+pub async fn set_scene(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    scene: Scene,
+) -> Result<WriteStatus> {
+    #[cfg(dev)]
+    info!("set_scene id: {id} value: {scene:#?}");
+    let gamma = Settings::load(&app)?.gamma;
+    set_led_scene(&state, &id, &scene, gamma).await
+}
+
+/// Writes a scene in the wire form the lamp understands, ending the running playlist
+/// and preview.
+pub async fn set_led_scene(
+    state: &AppState,
+    id: &PeripheralId,
+    scene: &Scene,
+    gamma: f64,
+) -> Result<WriteStatus> {
+    // a scene picked by hand ends the running playlist, and the preview that would paint
+    // over it; ending the preview falls back to the stored scene, which is this one
+    let (player, preview) = {
+        let mut ble_state = state.lock().await?;
+        (
+            ble_state.playlists.remove(id),
+            ble_state.previews.remove(id),
+        )
+    };
+    drop(preview);
+    if let Some(player) = player {
+        player.stop().await?;
+    }
+    let led = get_led(state, id).await?;
+    let wire = scene.to_wire(gamma, led.features);
+    Ok(led
+        .run_latest("scene", |led| async move { led.set_scene(wire).await })
+        .await?)
+}
+
+/// Plays a playlist on the lamp, replacing the running one.
+#[tauri::command]
+pub async fn play_playlist(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    playlist: Playlist,
+) -> Result<()> {
+    #[cfg(dev)]
+    info!("play_playlist id: {id} value: {playlist:#?}");
+    playlist.validate()?;
+    let gamma = Settings::load(&app)?.gamma;
+    let led = get_led(&state, &id).await?;
+    let player = state.lock().await?.playlists.remove(&id);
+    if let Some(player) = player {
+        player.stop().await?;
+    }
+    let player = PlaylistPlayer::start(app, led, playlist, gamma).await?;
+    state.lock().await?.playlists.insert(id, player);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_playlist(state: State<'_, AppState>, id: PeripheralId) -> Result<()> {
+    #[cfg(dev)]
+    info!("stop_playlist id: {id}");
+    let player = state.lock().await?.playlists.remove(&id);
+    if let Some(player) = player {
+        player.stop().await?;
+    }
+    Ok(())
+}
+
+/// What the firmware of the lamp supports beyond the original protocol.
+#[tauri::command]
+pub async fn get_features(state: State<'_, AppState>, id: PeripheralId) -> Result<Features> {
+    Ok(get_led(&state, &id).await?.features)
+}
+
+#[tauri::command]
+pub async fn get_playlist_state(
+    state: State<'_, AppState>,
+    id: PeripheralId,
+) -> Result<Option<PlaylistState>> {
+    let ble_state = state.lock().await?;
+    Ok(ble_state.playlists.get(&id).and_then(PlaylistPlayer::state))
+}
+
+#[tauri::command]
+pub async fn preview_color(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    color: Color,
+) -> Result<()> {
+    let color = color.corrected(Settings::load(&app)?.gamma);
+    let mut ble_state = state.lock().await?;
+    if ble_state
+        .previews
+        .get(&id)
+        .is_some_and(|preview| preview.send(color))
+    {
+        return Ok(());
+    }
     let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    led.control(command.into()).await?;
+    let preview = Preview::start(led.clone(), color)?;
+    ble_state.previews.insert(id, preview);
     Ok(())
 }
 
+/// Plays a scene through the preview channel, nothing is stored on the lamp.
 #[tauri::command]
-pub async fn set_scene(state: State<'_, AppState>, id: PeripheralId, scene: Value) -> Result<()> {
+pub async fn play_scene(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    scene: Scene,
+) -> Result<()> {
     #[cfg(dev)]
-    info!("set_scene id: {id} value: {scene:#?}");
-    let ble_state = state.lock().await;
+    info!("play_scene id: {id} value: {scene:#?}");
+    let gamma = Settings::load(&app)?.gamma;
+    let mut ble_state = state.lock().await?;
     let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    led.set_scene(scene).await?;
+    let preview = Preview::play(led.clone(), scene, gamma)?;
+    ble_state.previews.insert(id, preview);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_preview(state: State<'_, AppState>, id: PeripheralId) -> Result<()> {
+    #[cfg(dev)]
+    info!("stop_preview id: {id}");
+    let mut ble_state = state.lock().await?;
+    ble_state.previews.remove(&id);
     Ok(())
 }
 
 #[tauri::command]
-pub async fn get_scene(state: State<'_, AppState>, id: PeripheralId) -> Result<Value> {
+pub async fn get_scene(
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    force: Option<bool>,
+) -> Result<Value> {
     #[cfg(dev)]
     info!("get_scene id: {id}");
-    let ble_state = state.lock().await;
-    let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    let scene = led.get_scene().await?;
+    let force = force.unwrap_or(false);
+    let led = get_led(&state, &id).await?;
+    let scene = led
+        .run(|led| async move { led.get_scene(force).await })
+        .await?;
     Ok(scene)
 }
 
 #[tauri::command]
-pub async fn get_time_tasks(state: State<'_, AppState>, id: PeripheralId) -> Result<Value> {
+pub async fn get_time_tasks(
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    force: Option<bool>,
+) -> Result<Value> {
     #[cfg(dev)]
     info!("get_scene id: {id}");
-    let ble_state = state.lock().await;
-    let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    let scene = led.get_time_tasks().await?;
+    let force = force.unwrap_or(false);
+    let led = get_led(&state, &id).await?;
+    let scene = led
+        .run(|led| async move { led.get_time_tasks(force).await })
+        .await?;
     Ok(scene)
 }
 
+#[tauri::command]
+pub async fn get_cache_info(state: State<'_, AppState>, id: PeripheralId) -> Result<CacheInfo> {
+    Ok(get_led(&state, &id).await?.cache_info())
+}
+
 #[tauri::command]
 pub async fn get_state(state: State<'_, AppState>, id: PeripheralId) -> Result<String> {
     #[cfg(dev)]
     info!("get_scene id: {id}");
-    let ble_state = state.lock().await;
-    let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    let state = led.get_state().await?;
+    let led = get_led(&state, &id).await?;
+    let state = led.run(|led| async move { led.get_state().await }).await?;
     Ok(state)
 }
 
 #[tauri::command]
-pub async fn disconnect(state: State<'_, AppState>, id: PeripheralId) -> Result<()> {
+pub async fn disconnect(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: PeripheralId,
+) -> Result<()> {
     #[cfg(dev)]
     info!("disconnect id: {id}");
-    let mut ble_state = state.lock().await;
-    let led = ble_state.leds.remove(&id).ok_or(anyhow!("Led not found"))?;
+    let led = {
+        let mut ble_state = state.lock().await?;
+        ble_state.previews.remove(&id);
+        ble_state.playlists.remove(&id);
+        ble_state.leds.remove(&id).ok_or(anyhow!("Led not found"))?
+    };
     led.peripheral.disconnect().await?;
-    Ok(())
+    registry::touch(&app, &id).await
 }
 
 #[tauri::command]
 pub async fn set_timer(
     state: State<'_, AppState>,
     id: PeripheralId,
-    timer_event: Value,
+    timer_event: TimerEvent,
 ) -> Result<()> {
     #[cfg(dev)]
     info!("set_timer id: {id} value: {timer_event:#?}");
-    let ble_state = state.lock().await;
-    let led = ble_state.leds.get(&id).ok_or(anyhow!("Led not found"))?;
-    led.set_timer(timer_event).await?;
+    set_led_timer(&state, &id, timer_event).await
+}
+
+pub async fn set_led_timer(
+    state: &AppState,
+    id: &PeripheralId,
+    timer_event: TimerEvent,
+) -> Result<()> {
+    let led = get_led(state, id).await?;
+    led.run(|led| write_timer_event(led, timer_event)).await
+}
+
+/// Validates `timer_event` against the device task list and writes it,
+/// meant to run as one job so no other change slips in between.
+pub async fn write_timer_event(led: Led, timer_event: TimerEvent) -> Result<()> {
+    let tasks: Vec<TimeTask> = serde_json::from_value(led.get_time_tasks(false).await?)?;
+    let issues = validate_event(&tasks, &timer_event, Utc::now());
+    if !issues.is_empty() {
+        return Err(Error::TimeTasks(issues));
+    }
+    led.set_timer(serde_json::to_value(timer_event)?).await?;
     Ok(())
 }
+
+/// Brings the device task list to `desired` with the fewest add/remove events,
+/// undoing the applied events when one of them fails.
+#[tauri::command]
+pub async fn sync_time_tasks(
+    state: State<'_, AppState>,
+    id: PeripheralId,
+    desired: Vec<TimeTask>,
+) -> Result<TimeTaskSync> {
+    #[cfg(dev)]
+    info!("sync_time_tasks id: {id} value: {desired:#?}");
+    let led = get_led(&state, &id).await?;
+    led.run(|led| sync_tasks(led, desired)).await
+}
+
+async fn sync_tasks(led: Led, desired: Vec<TimeTask>) -> Result<TimeTaskSync> {
+    // the rollback relies on this list, so it is read from the lamp
+    let current: Vec<TimeTask> = serde_json::from_value(led.get_time_tasks(true).await?)?;
+    let diff = TimeTaskDiff::new(&current, &desired);
+
+    // expired once tasks already on the device are left alone
+    let issues = validate(&desired, Utc::now())
+        .into_iter()
+        .filter(|issue| !matches!(issue, TimeTaskIssue::PastOnce { name } if diff.unchanged.contains(name)))
+        .collect::<Vec<_>>();
+    if !issues.is_empty() {
+        return Err(Error::TimeTasks(issues));
+    }
+
+    let mut applied: Vec<TimerEvent> = vec![];
+    for event in diff.events() {
+        if let Err(e) = led.set_timer(serde_json::to_value(&event)?).await {
+            for undo in applied
+                .iter()
+                .rev()
+                .filter_map(|event| event.inverse(&current))
+            {
+                if let Err(rollback) = led.set_timer(serde_json::to_value(&undo)?).await {
+                    return Err(anyhow!(
+                        "sync time tasks failed: {e}, rollback failed: {rollback}"
+                    )
+                    .into());
+                }
+            }
+            return Err(e.into());
+        }
+        applied.push(event);
+    }
+    Ok(TimeTaskSync::from(&diff))
+}
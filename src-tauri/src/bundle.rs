@@ -0,0 +1,257 @@
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+use crate::dialog;
+use crate::error::Result;
+use crate::library::{self, LibraryScene};
+
+/// Version written by this build.
+///
+/// - 0: a bare scene or an array of scenes, as copy-pasted from the scene editor
+/// - 1: scenes with author, description and created date
+const BUNDLE_VERSION: u32 = 1;
+const BUNDLE_FILTER: &str = "Scene Bundle";
+const BUNDLE_EXTENSIONS: &[&str] = &["json"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SceneBundle {
+    pub version: u32,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub created: DateTime<Utc>,
+    pub scenes: Vec<LibraryScene>,
+}
+
+/// A bundle whose scenes are parsed one by one, so a broken scene does not sink the rest.
+#[derive(Deserialize)]
+struct RawBundle {
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    created: DateTime<Utc>,
+    scenes: Vec<Value>,
+}
+
+impl SceneBundle {
+    /// Reads a bundle of any known version and upgrades it to the current one.
+    ///
+    /// Scenes that cannot be read are left out and returned as skipped.
+    pub fn migrate(value: Value) -> anyhow::Result<(Self, Vec<SkippedScene>)> {
+        let version = match &value {
+            Value::Array(_) => 0,
+            Value::Object(object) if !object.contains_key("version") => 0,
+            Value::Object(object) => object
+                .get("version")
+                .and_then(Value::as_u64)
+                .ok_or(anyhow!("invalid bundle version"))?
+                as u32,
+            _ => bail!("invalid scene bundle"),
+        };
+
+        let bundle = match version {
+            0 => RawBundle {
+                author: None,
+                description: None,
+                created: Utc::now(),
+                scenes: match value {
+                    Value::Array(scenes) => scenes,
+                    scene => vec![scene],
+                },
+            },
+            BUNDLE_VERSION => serde_json::from_value(value)?,
+            version => bail!("scene bundle version {version} is newer than supported"),
+        };
+
+        let mut scenes = vec![];
+        let mut skipped = vec![];
+        for (index, scene) in bundle.scenes.into_iter().enumerate() {
+            let name = scene
+                .get("name")
+                .and_then(Value::as_str)
+                .map(String::from)
+                .unwrap_or_else(|| format!("#{}", index + 1));
+            match serde_json::from_value(scene) {
+                Ok(scene) => scenes.push(scene),
+                Err(e) => skipped.push(SkippedScene {
+                    name,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        let bundle = Self {
+            version: BUNDLE_VERSION,
+            author: bundle.author,
+            description: bundle.description,
+            created: bundle.created,
+            scenes,
+        };
+        Ok((bundle, skipped))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedScene {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub imported: Vec<String>,
+    pub skipped: Vec<SkippedScene>,
+}
+
+/// Saves the named library scenes as a bundle, returns false when the dialog is cancelled.
+#[tauri::command]
+pub async fn export_scenes(
+    app: AppHandle,
+    names: Vec<String>,
+    author: Option<String>,
+    description: Option<String>,
+) -> Result<bool> {
+    let scenes = library::list(&app)?
+        .into_iter()
+        .filter(|scene| names.contains(&scene.scene.name))
+        .map(|scene| LibraryScene {
+            is_builtin: false,
+            ..scene
+        })
+        .collect::<Vec<_>>();
+    if scenes.is_empty() {
+        return Err(anyhow!("no scenes to export").into());
+    }
+
+    let Some(path) =
+        dialog::save_file(&app, BUNDLE_FILTER, BUNDLE_EXTENSIONS, "scenes.json").await?
+    else {
+        return Ok(false);
+    };
+    let bundle = SceneBundle {
+        version: BUNDLE_VERSION,
+        author,
+        description,
+        created: Utc::now(),
+        scenes,
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&bundle)?).map_err(anyhow::Error::from)?;
+    Ok(true)
+}
+
+/// Adds the scenes of a bundle picked by the user to the library.
+///
+/// Invalid scenes and taken names are skipped and listed in the report,
+/// `None` when the dialog is cancelled.
+#[tauri::command]
+pub async fn import_scenes(app: AppHandle) -> Result<Option<ImportReport>> {
+    let Some(path) = dialog::pick_file(&app, BUNDLE_FILTER, BUNDLE_EXTENSIONS).await? else {
+        return Ok(None);
+    };
+    let data = std::fs::read(path).map_err(anyhow::Error::from)?;
+    let (bundle, skipped) = SceneBundle::migrate(serde_json::from_slice(&data)?)?;
+
+    let mut report = ImportReport {
+        author: bundle.author,
+        description: bundle.description,
+        skipped,
+        ..Default::default()
+    };
+    for scene in bundle.scenes {
+        let name = scene.scene.name.clone();
+        let result = match scene.scene.validate() {
+            Ok(()) => library::add(&app, scene).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(()) => report.imported.push(name),
+            Err(e) => report.skipped.push(SkippedScene {
+                name,
+                reason: e.to_string(),
+            }),
+        }
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn scene(name: &str) -> Value {
+        json!({ "name": name, "type": "solid", "color": "#ff0000" })
+    }
+
+    fn names(bundle: &SceneBundle) -> Vec<&str> {
+        bundle
+            .scenes
+            .iter()
+            .map(|scene| scene.scene.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn version_0_array() {
+        let (bundle, skipped) = SceneBundle::migrate(json!([scene("a"), scene("b")])).unwrap();
+        assert_eq!(bundle.version, BUNDLE_VERSION);
+        assert_eq!(names(&bundle), ["a", "b"]);
+        assert_eq!(bundle.author, None);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn version_0_single_scene() {
+        let (bundle, skipped) = SceneBundle::migrate(scene("a")).unwrap();
+        assert_eq!(names(&bundle), ["a"]);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn version_1() {
+        let created = "2024-05-01T12:00:00Z";
+        let (bundle, skipped) = SceneBundle::migrate(json!({
+            "version": 1,
+            "author": "me",
+            "created": created,
+            "scenes": [scene("a")],
+        }))
+        .unwrap();
+        assert_eq!(names(&bundle), ["a"]);
+        assert_eq!(bundle.author.as_deref(), Some("me"));
+        assert_eq!(bundle.description, None);
+        assert_eq!(bundle.created, created.parse::<DateTime<Utc>>().unwrap());
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn broken_scenes_are_skipped() {
+        let (bundle, skipped) =
+            SceneBundle::migrate(json!([scene("a"), { "name": "b" }, 42])).unwrap();
+        assert_eq!(names(&bundle), ["a"]);
+        let skipped = skipped
+            .iter()
+            .map(|scene| scene.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, ["b", "#3"]);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let bundle = |version| json!({ "version": version, "created": "2024-05-01T12:00:00Z", "scenes": [] });
+        let error = SceneBundle::migrate(bundle(json!(2))).unwrap_err();
+        assert!(error.to_string().contains("newer than supported"));
+        assert!(SceneBundle::migrate(bundle(json!("1"))).is_err());
+        assert!(SceneBundle::migrate(bundle(json!(-1))).is_err());
+        assert!(SceneBundle::migrate(json!("scene")).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+/// An RGB color.
+///
+/// Accepts `#rrggbb` strings, CSS color names, `[r, g, b]` arrays,
+/// `{ "h", "s", "v" }` and `{ "kelvin" }` objects, and always serializes as a hex string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ColorInput", into = "String")]
+pub struct Color(pub [u8; 3]);
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ColorInput {
+    Rgb([u8; 3]),
+    /// Hue in degrees, saturation and value from 0 to 1.
+    Hsv {
+        h: f64,
+        s: f64,
+        v: f64,
+    },
+    Kelvin {
+        kelvin: f64,
+    },
+    Text(String),
+}
+
+fn channel(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+impl Color {
+    pub fn from_hex(hex: &str) -> anyhow::Result<Self> {
+        let trimmed = hex.trim();
+        let digits = trimmed.strip_prefix('#').unwrap_or(trimmed);
+        // from_str_radix alone would let a leading sign through
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("invalid hex color: {hex}");
+        }
+        let digits = match digits.len() {
+            3 => digits.chars().flat_map(|c| [c, c]).collect::<String>(),
+            6 => digits.to_string(),
+            _ => bail!("invalid hex color: {hex}"),
+        };
+        let value =
+            u32::from_str_radix(&digits, 16).map_err(|_| anyhow!("invalid hex color: {hex}"))?;
+        Ok(Self([(value >> 16) as u8, (value >> 8) as u8, value as u8]))
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        NAMED_COLORS
+            .binary_search_by(|(item, _)| item.cmp(&name.as_str()))
+            .ok()
+            .map(|index| Self(NAMED_COLORS[index].1))
+    }
+
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Self {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let (saturation, value) = (saturation.clamp(0.0, 1.0), value.clamp(0.0, 1.0));
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        Self([r, g, b].map(|c| channel((c + m) * 255.0)))
+    }
+
+    /// Approximates the color of a black body, valid from 1000K to 40000K.
+    pub fn from_kelvin(kelvin: f64) -> Self {
+        let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+        let r = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let g = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        Self([channel(r), channel(g), channel(b)])
+    }
+
+    pub fn hex(&self) -> String {
+        let [r, g, b] = self.0;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    /// Applies gamma correction so equal steps look equally bright on the LEDs.
+    pub fn corrected(&self, gamma: f64) -> [u8; 3] {
+        if gamma == 1.0 {
+            return self.0;
+        }
+        self.0
+            .map(|value| channel((value as f64 / 255.0).powf(gamma) * 255.0))
+    }
+}
+
+impl TryFrom<ColorInput> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ColorInput) -> Result<Self, Self::Error> {
+        match value {
+            ColorInput::Rgb(rgb) => Ok(Color(rgb)),
+            ColorInput::Hsv { h, s, v } => Ok(Color::from_hsv(h, s, v)),
+            ColorInput::Kelvin { kelvin } => Ok(Color::from_kelvin(kelvin)),
+            ColorInput::Text(text) if text.trim_start().starts_with('#') => Color::from_hex(&text),
+            ColorInput::Text(text) => {
+                Color::from_name(&text).map_or_else(|| Color::from_hex(&text), Ok)
+            }
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(value: Color) -> Self {
+        value.hex()
+    }
+}
+
+/// CSS named colors, sorted by name.
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(value: serde_json::Value) -> anyhow::Result<Color> {
+        Ok(serde_json::from_value(value)?)
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(Color::from_hex("#ff8000").unwrap(), Color([255, 128, 0]));
+        assert_eq!(Color::from_hex(" F80 ").unwrap(), Color([255, 136, 0]));
+        assert_eq!(Color::from_hex("#abc").unwrap(), Color([170, 187, 204]));
+        for invalid in [
+            "", "#", "+12345", "-12345", "##fff", "#ff80", "#ggg", "#ff80000", "ＦＦ",
+        ] {
+            assert!(Color::from_hex(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn inputs() {
+        assert_eq!(parse(json!("#00ff00")).unwrap(), Color([0, 255, 0]));
+        assert_eq!(
+            parse(json!("RebeccaPurple")).unwrap(),
+            Color([102, 51, 153])
+        );
+        assert_eq!(parse(json!("ff0000")).unwrap(), Color([255, 0, 0]));
+        assert_eq!(parse(json!([1, 2, 3])).unwrap(), Color([1, 2, 3]));
+        assert_eq!(
+            parse(json!({ "h": 240.0, "s": 1.0, "v": 1.0 })).unwrap(),
+            Color([0, 0, 255])
+        );
+        assert_eq!(
+            parse(json!({ "h": -120.0, "s": 0.0, "v": 0.5 })).unwrap(),
+            Color([128, 128, 128])
+        );
+        assert_eq!(
+            parse(json!({ "kelvin": 6600.0 })).unwrap(),
+            Color([255, 255, 255])
+        );
+        assert!(parse(json!("notacolor")).is_err());
+        assert!(parse(json!([256, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn warm_and_cold_white() {
+        let [r, _, b] = Color::from_kelvin(2700.0).0;
+        assert!(r > b);
+        let [r, _, b] = Color::from_kelvin(10000.0).0;
+        assert!(r < b);
+    }
+
+    #[test]
+    fn round_trip() {
+        let color = Color([18, 52, 86]);
+        let value = serde_json::to_value(color).unwrap();
+        assert_eq!(value, "#123456");
+        assert_eq!(parse(value).unwrap(), color);
+    }
+
+    #[test]
+    fn names_are_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn gamma() {
+        assert_eq!(Color([0, 128, 255]).corrected(1.0), [0, 128, 255]);
+        assert_eq!(Color([0, 128, 255]).corrected(2.2), [0, 56, 255]);
+    }
+}
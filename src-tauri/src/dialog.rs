@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, FilePath};
+use tokio::sync::oneshot;
+
+use crate::error::Result;
+
+async fn into_path(receiver: oneshot::Receiver<Option<FilePath>>) -> Result<Option<PathBuf>> {
+    let Some(path) = receiver.await.map_err(anyhow::Error::from)? else {
+        return Ok(None);
+    };
+    Ok(Some(path.into_path().map_err(anyhow::Error::from)?))
+}
+
+/// Asks the user for a file to open, `None` when the dialog is cancelled.
+pub async fn pick_file(
+    app: &AppHandle,
+    name: &str,
+    extensions: &[&str],
+) -> Result<Option<PathBuf>> {
+    let (sender, receiver) = oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter(name, extensions)
+        .pick_file(move |path| {
+            let _ = sender.send(path);
+        });
+    into_path(receiver).await
+}
+
+/// Asks the user where to save a file, `None` when the dialog is cancelled.
+pub async fn save_file(
+    app: &AppHandle,
+    name: &str,
+    extensions: &[&str],
+    file_name: &str,
+) -> Result<Option<PathBuf>> {
+    let (sender, receiver) = oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter(name, extensions)
+        .set_file_name(file_name)
+        .save_file(move |path| {
+            let _ = sender.send(path);
+        });
+    into_path(receiver).await
+}
@@ -1,3 +1,5 @@
+use crate::timer::TimeTaskIssue;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -8,6 +10,10 @@ pub enum Error {
     Serde(#[from] serde_json::Error),
     #[error(transparent)]
     Tauri(#[from] tauri::Error),
+    #[error("invalid time tasks: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    TimeTasks(Vec<TimeTaskIssue>),
+    #[error("bluetooth is not initialized, call init first")]
+    NotInitialized,
 }
 
 // we must manually implement serde::Serialize
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::platform::PeripheralId;
+use chrono::{TimeDelta, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+use tokio::time::sleep;
+#[cfg(dev)]
+use tracing::info;
+use tracing::warn;
+
+use crate::ble::{control_led, set_led_scene, set_led_timer};
+use crate::error::Result;
+use crate::led::{Led, LedCommand};
+use crate::queue::WriteStatus;
+use crate::scene::Scene;
+use crate::settings::Settings;
+use crate::state::{get_led, AppState};
+use crate::store;
+use crate::timer::TimerEvent;
+
+const GROUPS_FILE: &str = "groups.json";
+/// How far ahead a common start is put, enough to measure the clocks and write the scene.
+pub const START_LEAD: Duration = Duration::from_secs(3);
+
+/// Lamps controlled together, e.g. the lamps of a room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    /// Ordered as the lamps are placed, effects moving across the group follow it.
+    pub devices: Vec<PeripheralId>,
+}
+
+/// The outcome of a group command on one lamp.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceResult<T> {
+    pub id: PeripheralId,
+    pub value: Option<T>,
+    /// Why the lamp failed, `None` when it succeeded.
+    pub error: Option<String>,
+}
+
+/// Looks the group up and picks the lamps to send to, `only` narrows them down
+/// to retry the lamps that failed before.
+pub fn members(
+    app: &AppHandle,
+    name: &str,
+    only: Option<Vec<PeripheralId>>,
+) -> Result<Vec<PeripheralId>> {
+    let groups: Vec<Group> = store::load(app, GROUPS_FILE)?;
+    let group = groups
+        .into_iter()
+        .find(|group| group.name == name)
+        .ok_or(anyhow!("group {name} not found"))?;
+    Ok(match only {
+        Some(only) => group
+            .devices
+            .into_iter()
+            .filter(|id| only.contains(id))
+            .collect(),
+        None => group.devices,
+    })
+}
+
+/// Runs `f` on every lamp at once, a failing lamp does not stop the others.
+pub async fn fan_out<T, F, Fut>(ids: Vec<PeripheralId>, f: F) -> Vec<DeviceResult<T>>
+where
+    F: Fn(PeripheralId) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    join_all(ids.into_iter().map(|id| {
+        let result = f(id.clone());
+        async move {
+            match result.await {
+                Ok(value) => DeviceResult {
+                    id,
+                    value: Some(value),
+                    error: None,
+                },
+                Err(e) => DeviceResult {
+                    id,
+                    value: None,
+                    error: Some(e.to_string()),
+                },
+            }
+        }
+    }))
+    .await
+}
+
+/// Writes a scene to each lamp at once, moving its start by the lamp's clock offset
+/// so the starts fall on the same instants by the host clock.
+///
+/// Firmware without the `startAt` feature gets the scene written so that the transfer
+/// ends when its start comes by the host clock instead, or right away when the start
+/// has passed. The transfer time is scaled from the last scene read or written.
+pub async fn write_synced(
+    state: &AppState,
+    gamma: f64,
+    scenes: Vec<(PeripheralId, Scene)>,
+) -> Vec<DeviceResult<WriteStatus>> {
+    let ids = scenes.iter().map(|(id, _)| id.clone()).collect();
+    let scenes = &scenes.into_iter().collect::<HashMap<_, _>>();
+    fan_out(ids, |id| async move {
+        let led = get_led(state, &id).await?;
+        let mut scene = scenes[&id].clone();
+        match scene.start_at {
+            Some(start_at) if led.features.start_at => {
+                // lamps that cannot tell are taken to be in step with the host
+                let offset = clock_offset(&led).await.unwrap_or_else(|e| {
+                    warn!("clock offset of {id} unknown: {e}");
+                    TimeDelta::zero()
+                });
+                scene.start_at = Some(start_at + offset);
+            }
+            Some(start_at) => {
+                // the scene starts once it is written, so the write begins early
+                // by the time the transfer is expected to take
+                let transfer = led
+                    .scene_write_time(&scene.to_wire(gamma, led.features))
+                    .unwrap_or_default();
+                if let Ok(wait) = (start_at - Utc::now()).to_std() {
+                    sleep(wait.saturating_sub(transfer)).await;
+                }
+            }
+            None => {}
+        }
+        set_led_scene(state, &id, &scene, gamma).await
+    })
+    .await
+}
+
+/// How far the lamp clock runs ahead of the host.
+async fn clock_offset(led: &Led) -> Result<TimeDelta> {
+    Ok(led
+        .run(|led| async move { led.clock_offset().await })
+        .await?)
+}
+
+#[tauri::command]
+pub async fn list_groups(app: AppHandle) -> Result<Vec<Group>> {
+    store::load(&app, GROUPS_FILE)
+}
+
+/// Adds a group or replaces the one with the same name.
+#[tauri::command]
+pub async fn save_group(app: AppHandle, group: Group) -> Result<()> {
+    if group.name.is_empty() {
+        return Err(anyhow!("group name is empty").into());
+    }
+    store::update(&app, GROUPS_FILE, |groups: &mut Vec<Group>| {
+        match groups.iter_mut().find(|item| item.name == group.name) {
+            Some(item) => *item = group,
+            None => groups.push(group),
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_group(app: AppHandle, name: String) -> Result<()> {
+    store::update(&app, GROUPS_FILE, |groups: &mut Vec<Group>| {
+        groups.retain(|item| item.name != name);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn group_control(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    command: LedCommand,
+    only: Option<Vec<PeripheralId>>,
+) -> Result<Vec<DeviceResult<WriteStatus>>> {
+    #[cfg(dev)]
+    info!("group_control name: {name}");
+    let state = &*state;
+    Ok(fan_out(members(&app, &name, only)?, |id| {
+        let command = command.clone();
+        async move { control_led(state, &id, command).await }
+    })
+    .await)
+}
+
+/// Writes the scene to every lamp with one start, so gradients play in step.
+///
+/// The start is the one of the scene or a moment from now, shifted by each lamp's
+/// clock offset so it falls on the same instant on every lamp.
+#[tauri::command]
+pub async fn group_set_scene(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    scene: Scene,
+    only: Option<Vec<PeripheralId>>,
+) -> Result<Vec<DeviceResult<WriteStatus>>> {
+    #[cfg(dev)]
+    info!("group_set_scene name: {name} value: {scene:#?}");
+    let gamma = Settings::load(&app)?.gamma;
+    let start_at = scene.start_at.unwrap_or(Utc::now() + START_LEAD);
+    let scenes = members(&app, &name, only)?
+        .into_iter()
+        .map(|id| {
+            let scene = Scene {
+                start_at: Some(start_at),
+                ..scene.clone()
+            };
+            (id, scene)
+        })
+        .collect();
+    Ok(write_synced(&state, gamma, scenes).await)
+}
+
+#[tauri::command]
+pub async fn group_set_timer(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    timer_event: TimerEvent,
+    only: Option<Vec<PeripheralId>>,
+) -> Result<Vec<DeviceResult<()>>> {
+    #[cfg(dev)]
+    info!("group_set_timer name: {name} value: {timer_event:#?}");
+    let state = &*state;
+    Ok(fan_out(members(&app, &name, only)?, |id| {
+        let timer_event = timer_event.clone();
+        async move { set_led_timer(state, &id, timer_event).await }
+    })
+    .await)
+}
@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use btleplug::api::Peripheral as _;
+use btleplug::platform::PeripheralId;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::time::timeout;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::state::AppState;
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// A probe taking longer counts as failed.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Lamps answering slower than this are degraded.
+const SLOW_LATENCY: Duration = Duration::from_millis(1000);
+/// Failed probes in a row after which a lamp is lost.
+const LOST_AFTER_FAILURES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthStatus {
+    Healthy,
+    /// Slow or missed a probe.
+    Degraded,
+    /// Stopped answering, the link is dropped so reconnecting can take over.
+    Lost,
+}
+
+/// Published as `health-{id}` when the status changes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    pub id: PeripheralId,
+    pub status: HealthStatus,
+    /// Round trip of the last successful probe.
+    pub latency: Option<u64>,
+    pub failures: u32,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl Health {
+    fn new(id: PeripheralId) -> Self {
+        Self {
+            id,
+            status: HealthStatus::Healthy,
+            latency: None,
+            failures: 0,
+            last_seen: None,
+            checked_at: Utc::now(),
+        }
+    }
+
+    fn update(&mut self, probe: Option<Duration>) {
+        self.checked_at = Utc::now();
+        match probe {
+            Some(latency) => {
+                self.latency = Some(latency.as_millis() as u64);
+                self.failures = 0;
+                self.last_seen = Some(self.checked_at);
+                self.status = if latency > SLOW_LATENCY {
+                    HealthStatus::Degraded
+                } else {
+                    HealthStatus::Healthy
+                };
+            }
+            None => {
+                self.failures += 1;
+                self.status = if self.failures >= LOST_AFTER_FAILURES {
+                    HealthStatus::Lost
+                } else {
+                    HealthStatus::Degraded
+                };
+            }
+        }
+    }
+}
+
+/// Probes every connected lamp once.
+async fn check(app: &AppHandle) -> Result<()> {
+    let Some(state) = app.try_state::<AppState>() else {
+        return Ok(());
+    };
+    let Ok((radio_on, leds)) = state.lock().await.map(|ble_state| {
+        let leds = ble_state.leds.values().cloned().collect::<Vec<_>>();
+        (ble_state.radio_on(), leds)
+    }) else {
+        return Ok(());
+    };
+    // probes would fail and drop every lamp while the radio is off
+    if !radio_on {
+        return Ok(());
+    }
+    // probes run as jobs so a long transfer is not mistaken for a lamp gone silent,
+    // lamps with jobs in flight are skipped until a later round
+    let probes = join_all(leds.iter().map(|led| async move {
+        if led.is_busy() {
+            return None;
+        }
+        led.run(|led| async move {
+            let latency = timeout(PROBE_TIMEOUT, led.probe()).await;
+            Ok::<_, anyhow::Error>(latency.ok().and_then(|probe| probe.ok()))
+        })
+        .await
+        .ok()
+    }))
+    .await;
+
+    let mut ble_state = state.lock().await?;
+    let connected = ble_state.leds.keys().cloned().collect::<Vec<_>>();
+    ble_state.health.retain(|id, _| connected.contains(id));
+    for (led, probe) in leds.iter().zip(probes) {
+        let Some(probe) = probe else {
+            continue;
+        };
+        let id = led.peripheral.id();
+        // disconnected by hand while probing
+        if !connected.contains(&id) {
+            continue;
+        }
+        let health = ble_state
+            .health
+            .entry(id.clone())
+            .or_insert_with(|| Health::new(id.clone()));
+        let before = health.status;
+        health.update(probe);
+        if health.status == before {
+            continue;
+        }
+        app.emit(&format!("health-{id}"), &*health)?;
+        if health.status == HealthStatus::Lost {
+            warn!("led {id} stopped answering");
+            let led = led.clone();
+            tauri::async_runtime::spawn(async move { led.peripheral.disconnect().await.ok() });
+        }
+    }
+    Ok(())
+}
+
+/// Probes the connected lamps for as long as the app runs.
+pub fn watch(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = check(&app).await {
+                warn!("health check error: {e}");
+            }
+        }
+    });
+}
+
+/// Health of the connected lamps, lamps not probed yet are left out.
+#[tauri::command]
+pub async fn get_health(state: State<'_, AppState>) -> Result<Vec<Health>> {
+    let ble_state = state.lock().await?;
+    Ok(ble_state.health.values().cloned().collect())
+}
@@ -1,33 +1,34 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
 use anyhow::{anyhow, bail, Result};
 use btleplug::{
-    api::{Characteristic, Peripheral as _, WriteType},
+    api::{Characteristic, Peripheral as _, ValueNotification, WriteType},
     platform::Peripheral,
 };
+use chrono::{DateTime, TimeDelta, Utc};
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tauri::{AppHandle, Emitter};
-use tracing::info;
+use tracing::{info, warn};
 use uuid::uuid;
 
-use crate::transmission::{msg::NotifyMessage, DataFromBytes, Transmission};
+use crate::queue::{JobQueue, WriteStatus};
+use crate::transmission::{
+    msg::{NotifyMessage, ReadMessage},
+    DataFromBytes, Transmission,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LedCommand {
     Open,
     Close,
     Reset,
 }
 
-impl From<&str> for LedCommand {
-    fn from(value: &str) -> Self {
-        match value {
-            "open" => LedCommand::Open,
-            "close" => LedCommand::Close,
-            "reset" => LedCommand::Reset,
-            _ => panic!("invalid control"),
-        }
-    }
-}
 impl Into<&[u8]> for LedCommand {
     fn into(self) -> &'static [u8] {
         match self {
@@ -38,6 +39,64 @@ impl Into<&[u8]> for LedCommand {
     }
 }
 
+/// A value read from the lamp and when it was read.
+#[derive(Debug, Clone)]
+pub struct Cached<T> {
+    pub value: T,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl<T> Cached<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value,
+            fetched_at: Utc::now(),
+        }
+    }
+}
+
+/// When the cached values were read, `None` when nothing is cached.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheInfo {
+    pub scene: Option<DateTime<Utc>>,
+    pub time_tasks: Option<DateTime<Utc>>,
+}
+
+/// What the firmware supports beyond the original protocol.
+///
+/// Read from the optional features characteristic when connecting, a JSON list of
+/// feature names. Lamps without the characteristic support none of them.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Features {
+    /// Transient colors through the `Preview` and `PreviewEnd` messages.
+    pub preview: bool,
+    /// `curve` and `hold` fields and zero length entries in gradients.
+    pub curves: bool,
+    /// Scenes starting at an absolute `startAt` time.
+    pub start_at: bool,
+}
+
+impl Features {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let names: Vec<String> = serde_json::from_slice(data)?;
+        let has = |name: &str| names.iter().any(|item| item == name);
+        Ok(Self {
+            preview: has("preview"),
+            curves: has("curves"),
+            start_at: has("startAt"),
+        })
+    }
+}
+
+/// Last known scene and task list, refreshed on `DataUpdate` and dropped after writes.
+#[derive(Debug, Default)]
+struct LedCache {
+    scene: Option<Cached<Value>>,
+    time_tasks: Option<Cached<Value>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Led {
     pub peripheral: Peripheral,
@@ -46,6 +105,14 @@ pub struct Led {
     pub state_characteristic: Characteristic,
     pub time_characteristic: Characteristic,
     pub time_task_transmission: Transmission<Value>,
+    /// Only present on firmware able to run a playlist by itself.
+    pub playlist_transmission: Option<Transmission<Value>>,
+    pub features: Features,
+    cache: Arc<Mutex<LedCache>>,
+    /// Operations of this lamp run one after the other, independent of other lamps.
+    jobs: Arc<JobQueue>,
+    /// Counts the preview sessions started, only the latest one may end the preview.
+    preview_session: Arc<AtomicU64>,
 }
 
 impl Led {
@@ -59,6 +126,8 @@ impl Led {
         let mut state_characteristic = None;
         let mut time_characteristic = None;
         let mut time_task_characteristic = None;
+        let mut playlist_characteristic = None;
+        let mut features_characteristic = None;
 
         if let Some(characteristics) = services
             .into_iter()
@@ -76,10 +145,23 @@ impl Led {
                     time_characteristic = Some(item);
                 } else if item.uuid == uuid!("f144af69-9642-97e1-d712-9448d1b450a1") {
                     time_task_characteristic = Some(item);
+                } else if item.uuid == uuid!("3d5a8e21-6f0b-4c97-b1e4-27c9a0d8f563") {
+                    playlist_characteristic = Some(item);
+                } else if item.uuid == uuid!("7b1e4c52-93d8-4a6f-b0e5-2c8f61d4a937") {
+                    features_characteristic = Some(item);
                 }
             }
         }
 
+        let features = match features_characteristic {
+            Some(characteristic) => Features::parse(&peripheral.read(&characteristic).await?)
+                .unwrap_or_else(|e| {
+                    warn!("unreadable features of {}: {e}", peripheral.id());
+                    Features::default()
+                }),
+            None => Features::default(),
+        };
+
         Ok(Self {
             scene_transmission: Transmission::new(
                 peripheral.clone(),
@@ -95,9 +177,49 @@ impl Led {
                 peripheral.clone(),
                 time_task_characteristic.ok_or(anyhow!("time task characteristic not found"))?,
             )?,
+            playlist_transmission: playlist_characteristic
+                .map(|characteristic| Transmission::new(peripheral.clone(), characteristic))
+                .transpose()?,
+            features,
+            cache: Arc::default(),
+            jobs: Arc::new(JobQueue::spawn()),
+            preview_session: Arc::default(),
             peripheral,
         })
     }
+    /// Queues an operation behind the ones already sent to this lamp and waits for its result.
+    ///
+    /// Transfers to one lamp never interleave, while other lamps are not held up.
+    pub async fn run<T, E, F, Fut>(&self, job: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(Led) -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>> + Send + 'static,
+        T: Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        self.jobs.run(job(self.clone())).await
+    }
+
+    /// Whether a job of this lamp is running or waiting.
+    pub fn is_busy(&self) -> bool {
+        self.jobs.is_busy()
+    }
+
+    /// Like `run`, but a newer job with the same `key` replaces this one while it is
+    /// still waiting, for writes where only the latest request matters.
+    pub async fn run_latest<E, F, Fut>(
+        &self,
+        key: &'static str,
+        job: F,
+    ) -> std::result::Result<WriteStatus, E>
+    where
+        F: FnOnce(Led) -> Fut,
+        Fut: Future<Output = std::result::Result<(), E>> + Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        self.jobs.run_latest(key, job(self.clone())).await
+    }
+
     pub async fn control(&self, command: LedCommand) -> Result<()> {
         self.check_connected().await?;
         Ok(self
@@ -124,19 +246,143 @@ impl Led {
             .await?)
     }
 
+    /// How far the lamp clock runs ahead of the host, read back from the time characteristic.
+    ///
+    /// The lamp is taken to have read its clock halfway through the round trip.
+    pub async fn clock_offset(&self) -> Result<TimeDelta> {
+        self.check_connected().await?;
+        let sent = Utc::now();
+        let data = self.peripheral.read(&self.time_characteristic).await?;
+        let received = Utc::now();
+        let lamp = i64::from_ne_bytes(
+            data.try_into()
+                .map_err(|_| anyhow!("unexpected time value length"))?,
+        );
+        let host = sent + (received - sent) / 2;
+        Ok(TimeDelta::milliseconds(lamp - host.timestamp_millis()))
+    }
+
     pub async fn set_scene(&self, scene: Value) -> Result<()> {
         self.check_connected().await?;
+        self.cache().scene = None;
         Ok(self.scene_transmission.write_value(&scene).await?)
     }
 
-    pub async fn get_scene(&self) -> Result<Value> {
+    /// How long writing `scene` should take, from the last transfer of a scene.
+    pub fn scene_write_time(&self, scene: &Value) -> Option<std::time::Duration> {
+        let size = serde_json::to_vec(scene).ok()?.len();
+        self.scene_transmission.estimate(size)
+    }
+
+    /// Shows a transient color without touching the stored scene.
+    pub async fn preview(&self, rgb: [u8; 3]) -> Result<()> {
+        self.check_preview()?;
         self.check_connected().await?;
-        Ok(self.scene_transmission.read_value().await?)
+        Ok(self
+            .peripheral
+            .write(
+                &self.scene_transmission.characteristic,
+                &ReadMessage::Preview(rgb).bytes(),
+                WriteType::WithoutResponse,
+            )
+            .await?)
     }
 
-    pub async fn get_time_tasks(&self) -> Result<Value> {
+    /// Leaves preview mode, the lamp goes back to its stored scene.
+    pub async fn end_preview(&self) -> Result<()> {
+        self.check_preview()?;
         self.check_connected().await?;
-        Ok(self.time_task_transmission.read_value().await?)
+        Ok(self
+            .peripheral
+            .write(
+                &self.scene_transmission.characteristic,
+                &ReadMessage::PreviewEnd.bytes(),
+                WriteType::WithResponse,
+            )
+            .await?)
+    }
+
+    /// Older firmware takes the preview messages for broken transfers.
+    pub fn check_preview(&self) -> Result<()> {
+        if !self.features.preview {
+            bail!("live preview not supported by the firmware");
+        }
+        Ok(())
+    }
+
+    /// Starts a preview session and returns its number.
+    pub fn next_preview_session(&self) -> u64 {
+        self.preview_session.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Whether no preview session was started after `session`.
+    pub fn is_latest_preview(&self, session: u64) -> bool {
+        self.preview_session.load(Ordering::SeqCst) == session
+    }
+
+    /// Uploads a playlist the firmware runs by itself, an empty one stops it.
+    pub async fn set_playlist(&self, playlist: Value) -> Result<()> {
+        self.check_connected().await?;
+        let transmission = self
+            .playlist_transmission
+            .as_ref()
+            .ok_or(anyhow!("playlist not supported by the firmware"))?;
+        Ok(transmission.write_value(&playlist).await?)
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, LedCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Forgets the cached values, e.g. after a reconnect.
+    pub fn invalidate(&self) {
+        *self.cache() = LedCache::default();
+    }
+
+    pub fn cache_info(&self) -> CacheInfo {
+        let cache = self.cache();
+        CacheInfo {
+            scene: cache.scene.as_ref().map(|cached| cached.fetched_at),
+            time_tasks: cache.time_tasks.as_ref().map(|cached| cached.fetched_at),
+        }
+    }
+
+    /// The stored scene, read from the lamp only when not cached or `force` is set.
+    pub async fn get_scene(&self, force: bool) -> Result<Value> {
+        if let Some(cached) = self.cached_scene().filter(|_| !force) {
+            return Ok(cached.value);
+        }
+        self.refresh_scene().await
+    }
+
+    fn cached_scene(&self) -> Option<Cached<Value>> {
+        self.cache().scene.clone()
+    }
+
+    async fn refresh_scene(&self) -> Result<Value> {
+        self.check_connected().await?;
+        let value = self.scene_transmission.read_value().await?;
+        self.cache().scene = Some(Cached::new(value.clone()));
+        Ok(value)
+    }
+
+    /// The task list, read from the lamp only when not cached or `force` is set.
+    pub async fn get_time_tasks(&self, force: bool) -> Result<Value> {
+        if let Some(cached) = self.cached_time_tasks().filter(|_| !force) {
+            return Ok(cached.value);
+        }
+        self.refresh_time_tasks().await
+    }
+
+    fn cached_time_tasks(&self) -> Option<Cached<Value>> {
+        self.cache().time_tasks.clone()
+    }
+
+    async fn refresh_time_tasks(&self) -> Result<Value> {
+        self.check_connected().await?;
+        let value = self.time_task_transmission.read_value().await?;
+        self.cache().time_tasks = Some(Cached::new(value.clone()));
+        Ok(value)
     }
 
     pub async fn get_state(&self) -> Result<String> {
@@ -144,6 +390,14 @@ impl Led {
         let state = self.peripheral.read(&self.state_characteristic).await?;
         Ok(String::from_utf8(state)?)
     }
+    /// Reads the state characteristic and returns the round trip time,
+    /// unlike `check_connected` this fails as soon as the lamp stops answering.
+    pub async fn probe(&self) -> Result<std::time::Duration> {
+        let start = std::time::Instant::now();
+        self.peripheral.read(&self.state_characteristic).await?;
+        Ok(start.elapsed())
+    }
+
     pub async fn subscribe(&self) -> Result<()> {
         self.check_connected().await?;
         self.peripheral
@@ -163,35 +417,76 @@ impl Led {
         let led = self.clone();
 
         tauri::async_runtime::spawn(async move {
-            let mut notifiactions = led.peripheral.notifications().await?;
+            let mut notifiactions = match led.peripheral.notifications().await {
+                Ok(notifiactions) => notifiactions,
+                Err(e) => {
+                    warn!("notifications of {} unavailable: {e}", led.peripheral.id());
+                    return;
+                }
+            };
             while let Some(notification) = notifiactions.next().await {
-                if notification.uuid == led.state_characteristic.uuid {
-                    let value = String::from_utf8(notification.value)?;
-                    app_handle.emit(&format!("state-{}", led.peripheral.id()), value)?;
-                } else if notification.uuid == led.scene_transmission.characteristic.uuid {
-                    let (msg, _) = NotifyMessage::from_data(&notification.value);
-                    if let NotifyMessage::DataUpdate = msg {
-                        let value = led.scene_transmission.read_value().await?;
-                        app_handle.emit(&format!("scene-{}", led.peripheral.id()), value)?;
-                    } else if let NotifyMessage::Error(e) = msg {
-                        app_handle.emit(&format!("error-{}", led.peripheral.id()), e)?;
-                    }
-                } else if notification.uuid == led.time_task_transmission.characteristic.uuid {
-                    let (msg, _) = NotifyMessage::from_data(&notification.value);
-                    if let NotifyMessage::DataUpdate = msg {
-                        let value = led.time_task_transmission.read_value().await?;
-                        app_handle.emit(&format!("time-tasks-{}", led.peripheral.id()), value)?;
-                    } else if let NotifyMessage::Error(e) = msg {
-                        app_handle.emit(&format!("error-{}", led.peripheral.id()), e)?;
-                    }
+                if let Err(e) = led.on_notification(&app_handle, notification) {
+                    warn!("notification of {} failed: {e}", led.peripheral.id());
                 }
             }
-            Ok::<(), anyhow::Error>(())
         });
 
         Ok(())
     }
 
+    fn on_notification(
+        &self,
+        app_handle: &AppHandle,
+        notification: ValueNotification,
+    ) -> Result<()> {
+        let id = self.peripheral.id();
+        if notification.uuid == self.state_characteristic.uuid {
+            let value = String::from_utf8(notification.value)?;
+            app_handle.emit(&format!("state-{id}"), value)?;
+        } else if notification.uuid == self.scene_transmission.characteristic.uuid {
+            let (msg, _) = NotifyMessage::from_data(&notification.value);
+            if let NotifyMessage::DataUpdate = msg {
+                self.refresh(app_handle, "scene", |led| async move {
+                    led.refresh_scene().await
+                });
+            } else if let NotifyMessage::Error(e) = msg {
+                app_handle.emit(&format!("error-{id}"), e)?;
+            }
+        } else if notification.uuid == self.time_task_transmission.characteristic.uuid {
+            let (msg, _) = NotifyMessage::from_data(&notification.value);
+            if let NotifyMessage::DataUpdate = msg {
+                self.refresh(app_handle, "time-tasks", |led| async move {
+                    led.refresh_time_tasks().await
+                });
+            } else if let NotifyMessage::Error(e) = msg {
+                app_handle.emit(&format!("error-{id}"), e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a changed value in a job of its own, so it does not interleave with
+    /// a transfer, and emits it as `{event}-{id}`. The notification loop does not
+    /// wait for it.
+    fn refresh<F, Fut>(&self, app_handle: &AppHandle, event: &'static str, read: F)
+    where
+        F: FnOnce(Led) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let (led, app_handle) = (self.clone(), app_handle.clone());
+        tauri::async_runtime::spawn(async move {
+            let id = led.peripheral.id();
+            let emitted = async {
+                let value = led.run(read).await?;
+                app_handle.emit(&format!("{event}-{id}"), value)?;
+                Ok::<_, anyhow::Error>(())
+            };
+            if let Err(e) = emitted.await {
+                warn!("refresh {event} of {id} failed: {e}");
+            }
+        });
+    }
+
     pub async fn check_connected(&self) -> Result<()> {
         if !self.peripheral.is_connected().await? {
             bail!("led device not connected")
@@ -201,6 +496,7 @@ impl Led {
 
     pub async fn set_timer(&self, time_task: Value) -> Result<()> {
         self.check_connected().await?;
+        self.cache().time_tasks = None;
         Ok(self.time_task_transmission.write_value(&time_task).await?)
     }
 }
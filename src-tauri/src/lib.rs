@@ -1,12 +1,58 @@
+mod adapter;
 mod ble;
+mod bundle;
+mod color;
+mod dialog;
 mod error;
+mod group;
+mod health;
 mod led;
+mod library;
+mod palette;
+mod playlist;
+mod preview;
+mod queue;
+mod reconnect;
+mod registry;
+mod render;
+mod scan;
+mod scene;
+mod scheduler;
+mod settings;
+mod solar;
 mod state;
+mod store;
+mod timer;
+use adapter::{get_adapter_state, list_adapters, select_adapter};
 use ble::{
-    connect, control, disconnect, get_devices, get_scene, get_state, get_time_tasks, init,
-    set_scene, set_timer, start_scan, stop_scan,
+    connect, control, disconnect, get_cache_info, get_devices, get_features, get_playlist_state,
+    get_scene, get_state, get_time_tasks, init, play_playlist, play_scene, preview_color,
+    set_scene, set_timer, start_scan, stop_playlist, stop_preview, stop_scan, sync_time_tasks,
 };
 mod transmission;
+mod wave;
+use bundle::{export_scenes, import_scenes};
+use group::{
+    delete_group, group_control, group_set_scene, group_set_timer, list_groups, save_group,
+};
+use health::get_health;
+use library::{
+    create_scene, delete_scene, import_legacy_scenes, list_scenes, rename_scene, update_scene,
+};
+use palette::scene_from_image;
+use playlist::{delete_playlist, list_playlists, save_playlist};
+use reconnect::{get_reconnect_policies, set_reconnect_policy};
+use registry::{export_registry, get_registry, register_device, unregister_device, update_device};
+use render::render_scene;
+use scheduler::{
+    add_schedule_rule, get_schedule, get_schedule_history, remove_schedule_rule, set_holidays,
+    update_schedule_rule,
+};
+use settings::{get_settings, set_settings};
+use solar::{add_solar_task, get_solar_tasks, remove_solar_task};
+use tauri::Manager;
+use timer::validate_time_tasks;
+use wave::group_wave;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -18,6 +64,9 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             init,
+            list_adapters,
+            select_adapter,
+            get_adapter_state,
             start_scan,
             stop_scan,
             get_devices,
@@ -25,11 +74,69 @@ pub fn run() {
             control,
             set_scene,
             get_scene,
+            preview_color,
+            play_scene,
+            render_scene,
+            stop_preview,
+            play_playlist,
+            stop_playlist,
+            get_playlist_state,
+            list_playlists,
+            save_playlist,
+            delete_playlist,
             disconnect,
+            list_groups,
+            save_group,
+            delete_group,
+            group_control,
+            group_set_scene,
+            group_set_timer,
+            group_wave,
+            get_registry,
+            register_device,
+            update_device,
+            unregister_device,
+            export_registry,
+            get_reconnect_policies,
+            set_reconnect_policy,
             get_state,
+            get_cache_info,
+            get_features,
+            get_health,
             set_timer,
+            validate_time_tasks,
+            sync_time_tasks,
             get_time_tasks,
+            scene_from_image,
+            list_scenes,
+            create_scene,
+            update_scene,
+            rename_scene,
+            delete_scene,
+            import_legacy_scenes,
+            export_scenes,
+            import_scenes,
+            get_settings,
+            set_settings,
+            get_solar_tasks,
+            add_solar_task,
+            remove_solar_task,
+            get_schedule,
+            add_schedule_rule,
+            update_schedule_rule,
+            remove_schedule_rule,
+            set_holidays,
+            get_schedule_history,
         ])
+        .setup(|app| {
+            app.manage(state::AppState::default());
+            adapter::watch(app.handle().clone());
+            reconnect::watch(app.handle().clone());
+            health::watch(app.handle().clone());
+            solar::watch(app.handle().clone());
+            scheduler::watch(app.handle().clone());
+            Ok(())
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,192 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+use crate::color::Color;
+use crate::error::Result;
+use crate::scene::{ColorDuration, Scene, SceneKind};
+use crate::store;
+
+const LIBRARY_FILE: &str = "scenes.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryScene {
+    #[serde(flatten)]
+    pub scene: Scene,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub is_builtin: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Library {
+    scenes: Vec<LibraryScene>,
+    /// The scenes of the webview storage have been imported.
+    legacy_imported: bool,
+}
+
+/// Scenes shipped with the app, they are never stored and cannot be changed or deleted.
+pub fn builtin_scenes() -> Vec<LibraryScene> {
+    vec![
+        LibraryScene {
+            scene: Scene {
+                name: "default".into(),
+                auto_on: false,
+                start_at: None,
+                kind: SceneKind::Solid {
+                    color: Color([0xff, 0xff, 0xff]),
+                },
+            },
+            description: Some("默认场景".into()),
+            is_builtin: true,
+        },
+        LibraryScene {
+            scene: Scene {
+                name: "Breathe".into(),
+                auto_on: false,
+                start_at: None,
+                kind: SceneKind::Gradient {
+                    colors: vec![
+                        ColorDuration::new(Color([0xfa, 0x8c, 0x16]), 2),
+                        ColorDuration::new(Color([0x00, 0x00, 0x00]), 2),
+                    ],
+                    linear: true,
+                },
+            },
+            description: Some("呼吸灯".into()),
+            is_builtin: true,
+        },
+    ]
+}
+
+fn is_builtin(name: &str) -> bool {
+    builtin_scenes()
+        .iter()
+        .any(|scene| scene.scene.name == name)
+}
+
+impl Library {
+    fn load(app: &AppHandle) -> Result<Self> {
+        store::load(app, LIBRARY_FILE)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        is_builtin(name) || self.scenes.iter().any(|scene| scene.scene.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut LibraryScene> {
+        if is_builtin(name) {
+            return Err(anyhow!("builtin scene {name} cannot be changed").into());
+        }
+        Ok(self
+            .scenes
+            .iter_mut()
+            .find(|scene| scene.scene.name == name)
+            .ok_or(anyhow!("scene {name} not found"))?)
+    }
+
+    fn add(&mut self, scene: LibraryScene) -> Result<()> {
+        scene.scene.validate()?;
+        if self.contains(&scene.scene.name) {
+            return Err(anyhow!("scene {} already exists", scene.scene.name).into());
+        }
+        self.scenes.push(LibraryScene {
+            is_builtin: false,
+            ..scene
+        });
+        Ok(())
+    }
+}
+
+/// All scenes, builtin ones first.
+pub fn list(app: &AppHandle) -> Result<Vec<LibraryScene>> {
+    let mut scenes = builtin_scenes();
+    scenes.extend(Library::load(app)?.scenes);
+    Ok(scenes)
+}
+
+#[tauri::command]
+pub async fn list_scenes(app: AppHandle) -> Result<Vec<LibraryScene>> {
+    list(&app)
+}
+
+/// Adds a scene, failing when the name is taken.
+pub async fn add(app: &AppHandle, scene: LibraryScene) -> Result<()> {
+    store::update(app, LIBRARY_FILE, |library: &mut Library| {
+        library.add(scene)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn create_scene(app: AppHandle, scene: LibraryScene) -> Result<()> {
+    add(&app, scene).await
+}
+
+#[tauri::command]
+pub async fn update_scene(app: AppHandle, name: String, scene: LibraryScene) -> Result<()> {
+    scene.scene.validate()?;
+    store::update(&app, LIBRARY_FILE, |library: &mut Library| {
+        if scene.scene.name != name && library.contains(&scene.scene.name) {
+            return Err(anyhow!("scene {} already exists", scene.scene.name).into());
+        }
+        *library.get_mut(&name)? = LibraryScene {
+            is_builtin: false,
+            ..scene
+        };
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn rename_scene(app: AppHandle, name: String, new_name: String) -> Result<()> {
+    store::update(&app, LIBRARY_FILE, |library: &mut Library| {
+        if library.contains(&new_name) {
+            return Err(anyhow!("scene {new_name} already exists").into());
+        }
+        let scene = &mut library.get_mut(&name)?.scene;
+        scene.name = new_name;
+        Ok(scene.validate()?)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_scene(app: AppHandle, name: String) -> Result<()> {
+    store::update(&app, LIBRARY_FILE, |library: &mut Library| {
+        library.get_mut(&name)?;
+        library.scenes.retain(|scene| scene.scene.name != name);
+        Ok(())
+    })
+    .await
+}
+
+/// Imports the scenes kept in the webview storage, only the first call has an effect.
+///
+/// Returns the number of imported scenes, builtin, invalid and already existing ones are skipped.
+#[tauri::command]
+pub async fn import_legacy_scenes(app: AppHandle, scenes: Vec<Value>) -> Result<usize> {
+    store::update(&app, LIBRARY_FILE, |library: &mut Library| {
+        if library.legacy_imported {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for scene in scenes
+            .into_iter()
+            .filter_map(|scene| serde_json::from_value::<LibraryScene>(scene).ok())
+            .filter(|scene| !scene.is_builtin)
+        {
+            if library.add(scene).is_ok() {
+                count += 1;
+            }
+        }
+        library.legacy_imported = true;
+        Ok(count)
+    })
+    .await
+}
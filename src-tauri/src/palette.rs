@@ -0,0 +1,202 @@
+use std::{cmp::Reverse, path::Path};
+
+use anyhow::anyhow;
+use image::RgbImage;
+use tauri::AppHandle;
+
+use crate::color::Color;
+use crate::dialog;
+use crate::error::Result;
+use crate::scene::{ColorDuration, Scene, SceneKind, MAX_NAME_LEN};
+
+/// Images are shrunk to fit in this size before quantization,
+/// the palette of a thumbnail is close enough and much cheaper to compute.
+const SAMPLE_SIZE: u32 = 128;
+/// Most colors taken from one image, more than a gradient editor can show.
+pub const MAX_COLORS: usize = 16;
+
+/// Extracts up to `count` dominant colors with median cut, most common color first.
+///
+/// `count` is capped at `MAX_COLORS`.
+pub fn dominant_colors(image: &RgbImage, count: usize) -> Vec<Color> {
+    let count = count.min(MAX_COLORS);
+    let pixels = image.pixels().map(|pixel| pixel.0).collect::<Vec<_>>();
+    if pixels.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        // split the box whose widest channel covers the most pixels
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range as usize * pixels.len())
+            })
+            .filter(|(_, _, score)| *score > 0)
+            .max_by_key(|(_, _, score)| *score)
+            .map(|(index, channel, _)| (index, channel))
+        else {
+            break;
+        };
+
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|pixels| Reverse(pixels.len()));
+    boxes.iter().map(|pixels| average(pixels)).collect()
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                (min.min(pixel[channel]), max.max(pixel[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+fn average(pixels: &[[u8; 3]]) -> Color {
+    let mut sum = [0u64; 3];
+    for pixel in pixels {
+        for channel in 0..3 {
+            sum[channel] += pixel[channel] as u64;
+        }
+    }
+    let len = pixels.len().max(1) as u64;
+    Color(sum.map(|value| (value / len) as u8))
+}
+
+pub fn scene_from_path(path: &Path, count: usize, duration: u32) -> anyhow::Result<Scene> {
+    let image = image::open(path)?
+        .thumbnail(SAMPLE_SIZE, SAMPLE_SIZE)
+        .to_rgb8();
+    let colors = dominant_colors(&image, count);
+    if colors.is_empty() {
+        return Err(anyhow!("no colors found in image"));
+    }
+
+    let scene = Scene {
+        name: scene_name(path),
+        auto_on: false,
+        start_at: None,
+        kind: SceneKind::Gradient {
+            colors: colors
+                .into_iter()
+                .map(|color| ColorDuration::new(color, duration))
+                .collect(),
+            linear: true,
+        },
+    };
+    scene.validate()?;
+    Ok(scene)
+}
+
+/// The file name without extension, cut to the length the scene editor allows.
+fn scene_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = stem
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LEN)
+        .collect::<String>();
+    match name.trim_end() {
+        "" => "image".into(),
+        name => name.into(),
+    }
+}
+
+/// Lets the user pick an image and turns its dominant colors into a gradient scene.
+///
+/// Returns `None` when the dialog is cancelled.
+#[tauri::command]
+pub async fn scene_from_image(
+    app: AppHandle,
+    count: usize,
+    duration: u32,
+) -> Result<Option<Scene>> {
+    if count == 0 {
+        return Err(anyhow!("at least one color is needed").into());
+    }
+    if count > MAX_COLORS {
+        return Err(anyhow!("at most {MAX_COLORS} colors can be taken from an image").into());
+    }
+    if duration == 0 {
+        return Err(anyhow!("color duration must be at least one second").into());
+    }
+    let Some(path) =
+        dialog::pick_file(&app, "Image", &["png", "jpg", "jpeg", "webp", "bmp"]).await?
+    else {
+        return Ok(None);
+    };
+
+    let scene =
+        tauri::async_runtime::spawn_blocking(move || scene_from_path(&path, count, duration))
+            .await??;
+    Ok(Some(scene))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    /// A 4x4 image, the first `red` pixels red and the rest blue.
+    fn image(red: u32) -> RgbImage {
+        RgbImage::from_fn(4, 4, |x, y| Rgb(if y * 4 + x < red { RED } else { BLUE }))
+    }
+
+    #[test]
+    fn splits_at_the_median() {
+        assert_eq!(dominant_colors(&image(8), 2), [Color(RED), Color(BLUE)]);
+        // the larger box comes first
+        assert_eq!(
+            dominant_colors(&image(12), 3),
+            [Color(RED), Color(RED), Color(BLUE)]
+        );
+    }
+
+    #[test]
+    fn one_color_is_the_average() {
+        assert_eq!(dominant_colors(&image(8), 1), [Color([127, 0, 127])]);
+    }
+
+    #[test]
+    fn stops_when_no_box_can_be_split() {
+        assert_eq!(dominant_colors(&image(16), 5), [Color(RED)]);
+        assert!(dominant_colors(&image(8), 0).is_empty());
+        assert!(dominant_colors(&RgbImage::new(0, 0), 3).is_empty());
+    }
+
+    #[test]
+    fn count_is_capped() {
+        let noise = RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 0]));
+        assert_eq!(dominant_colors(&noise, 1000).len(), MAX_COLORS);
+    }
+
+    #[test]
+    fn names_are_cut_and_cleaned() {
+        assert_eq!(scene_name(Path::new("/tmp/ sunset\u{7}.png")), "sunset");
+        assert_eq!(scene_name(Path::new("/tmp/.png")), ".png");
+        assert_eq!(scene_name(Path::new("/tmp/   .jpg")), "image");
+        let long = format!("/tmp/{}.png", "a".repeat(40));
+        assert_eq!(scene_name(Path::new(&long)).chars().count(), MAX_NAME_LEN);
+    }
+}
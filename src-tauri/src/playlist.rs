@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::api::Peripheral as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::led::{Features, Led};
+use crate::scene::Scene;
+use crate::store;
+
+const PLAYLISTS_FILE: &str = "playlists.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistItem {
+    pub scene: Scene,
+    /// Seconds the scene stays on before the next one.
+    pub dwell: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub items: Vec<PlaylistItem>,
+    /// Starts over after the last item instead of staying on it.
+    #[serde(default, rename = "loop")]
+    pub repeat: bool,
+    /// Plays the items in a new random order on every pass.
+    #[serde(default)]
+    pub shuffle: bool,
+}
+
+/// Published as `playlist-{id}` whenever the playing item changes, `null` once the playlist ends.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistState {
+    pub name: String,
+    pub index: usize,
+    pub scene: String,
+    /// `None` on the last item of a playlist that does not loop.
+    pub next_change: Option<DateTime<Utc>>,
+    /// The firmware runs the playlist, the host only follows along.
+    pub uploaded: bool,
+}
+
+impl Playlist {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(anyhow!("playlist name is empty").into());
+        }
+        if self.items.is_empty() {
+            return Err(anyhow!("playlist {} has no items", self.name).into());
+        }
+        for item in &self.items {
+            if item.dwell == 0 {
+                return Err(anyhow!("scene {} has no dwell time", item.scene.name).into());
+            }
+            item.scene.validate()?;
+        }
+        Ok(())
+    }
+
+    /// The firmware runs items in order, so shuffled playlists are always host driven.
+    fn to_wire(&self, gamma: f64, features: Features) -> Value {
+        json!({
+            "name": self.name,
+            "loop": self.repeat,
+            "items": self.items.iter().map(|item| json!({
+                "scene": item.scene.to_wire(gamma, features),
+                "dwell": item.dwell,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    fn order(&self) -> Vec<usize> {
+        let mut order = (0..self.items.len()).collect::<Vec<_>>();
+        if self.shuffle {
+            order.shuffle(&mut rand::thread_rng());
+        }
+        order
+    }
+}
+
+/// A playlist running on one lamp.
+///
+/// Dropping it stops a host driven playlist, uploaded ones keep running
+/// on the firmware until `stop` is called or another playlist is uploaded.
+#[derive(Debug)]
+pub struct PlaylistPlayer {
+    task: JoinHandle<()>,
+    uploaded: bool,
+    state: watch::Receiver<Option<PlaylistState>>,
+    led: Led,
+    app: AppHandle,
+}
+
+impl PlaylistPlayer {
+    /// Uploads the playlist when the firmware supports it, otherwise
+    /// writes every scene from the host when its turn comes.
+    pub async fn start(app: AppHandle, led: Led, playlist: Playlist, gamma: f64) -> Result<Self> {
+        let uploaded = led.playlist_transmission.is_some() && !playlist.shuffle;
+        if uploaded {
+            let wire = playlist.to_wire(gamma, led.features);
+            led.run(|led| async move { led.set_playlist(wire).await })
+                .await?;
+        }
+
+        let (sender, state) = watch::channel(None);
+        let task = tauri::async_runtime::spawn({
+            let (app, led) = (app.clone(), led.clone());
+            async move {
+                let event = format!("playlist-{}", led.peripheral.id());
+                'outer: loop {
+                    let order = playlist.order();
+                    for (position, &index) in order.iter().enumerate() {
+                        let item = &playlist.items[index];
+                        if !uploaded {
+                            let wire = item.scene.to_wire(gamma, led.features);
+                            let written = led
+                                .run(|led| async move { led.set_scene(wire).await })
+                                .await;
+                            if let Err(e) = written {
+                                warn!("playlist {} stopped: {e}", playlist.name);
+                                break 'outer;
+                            }
+                        }
+                        let last = position + 1 == order.len() && !playlist.repeat;
+                        let state = PlaylistState {
+                            name: playlist.name.clone(),
+                            index,
+                            scene: item.scene.name.clone(),
+                            next_change: (!last)
+                                .then(|| Utc::now() + TimeDelta::seconds(item.dwell as i64)),
+                            uploaded,
+                        };
+                        if let Err(e) = app.emit(&event, &state) {
+                            warn!("playlist event error: {e}");
+                        }
+                        sender.send_replace(Some(state));
+                        if last {
+                            break 'outer;
+                        }
+                        sleep(Duration::from_secs(item.dwell as u64)).await;
+                    }
+                }
+                sender.send_replace(None);
+                app.emit(&event, Value::Null).ok();
+            }
+        });
+
+        Ok(Self {
+            task,
+            uploaded,
+            state,
+            led,
+            app,
+        })
+    }
+
+    pub fn state(&self) -> Option<PlaylistState> {
+        self.state.borrow().clone()
+    }
+
+    /// Stops the playlist, also on the firmware when it was uploaded.
+    pub async fn stop(self) -> Result<()> {
+        self.task.abort();
+        if self.uploaded {
+            self.led
+                .run(|led| async move { led.set_playlist(json!({ "items": [] })).await })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PlaylistPlayer {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.app
+            .emit(
+                &format!("playlist-{}", self.led.peripheral.id()),
+                Value::Null,
+            )
+            .ok();
+    }
+}
+
+#[tauri::command]
+pub async fn list_playlists(app: AppHandle) -> Result<Vec<Playlist>> {
+    store::load(&app, PLAYLISTS_FILE)
+}
+
+/// Adds a playlist or replaces the one with the same name.
+#[tauri::command]
+pub async fn save_playlist(app: AppHandle, playlist: Playlist) -> Result<()> {
+    playlist.validate()?;
+    store::update(&app, PLAYLISTS_FILE, |playlists: &mut Vec<Playlist>| {
+        match playlists.iter_mut().find(|item| item.name == playlist.name) {
+            Some(item) => *item = playlist,
+            None => playlists.push(playlist),
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_playlist(app: AppHandle, name: String) -> Result<()> {
+    store::update(&app, PLAYLISTS_FILE, |playlists: &mut Vec<Playlist>| {
+        playlists.retain(|item| item.name != name);
+        Ok(())
+    })
+    .await
+}
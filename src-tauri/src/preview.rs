@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::Utc;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, timeout};
+use tracing::warn;
+
+use crate::led::Led;
+use crate::scene::Scene;
+
+/// Minimum gap between two frames written to the lamp.
+const FRAME_INTERVAL: Duration = Duration::from_millis(40);
+/// Preview ends by itself when no frame arrives for this long,
+/// so a closed editor never leaves the lamp stuck on a preview color.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A running preview session of one lamp.
+///
+/// Frames are pushed into a watch channel and only the latest one is sent,
+/// so dragging a color picker never queues up writes. Each frame waits in the
+/// lamp queue, so it never lands in the middle of a scene transfer. Dropping
+/// the session ends the preview and the lamp restores its stored scene, unless
+/// a newer session of the lamp has started in the meantime.
+#[derive(Debug)]
+pub struct Preview {
+    frame: Arc<watch::Sender<[u8; 3]>>,
+}
+
+impl Preview {
+    /// Fails right away on firmware without preview support.
+    pub fn start(led: Led, rgb: [u8; 3]) -> Result<Self> {
+        led.check_preview()?;
+        let session = led.next_preview_session();
+        let (frame, mut receiver) = watch::channel(rgb);
+        receiver.mark_changed();
+
+        tauri::async_runtime::spawn(async move {
+            let mut last = None;
+            while let Ok(Ok(())) = timeout(IDLE_TIMEOUT, receiver.changed()).await {
+                let rgb = *receiver.borrow_and_update();
+                if last == Some(rgb) {
+                    continue;
+                }
+                let sent = led.run(|led| async move { led.preview(rgb).await });
+                if let Err(e) = sent.await {
+                    warn!("preview frame error: {e}");
+                    break;
+                }
+                last = Some(rgb);
+                sleep(FRAME_INTERVAL).await;
+            }
+            // checked in the queue, a session replacing this one may have written its
+            // first frame already
+            let ended = led.run(|led| async move {
+                if !led.is_latest_preview(session) {
+                    return Ok(());
+                }
+                led.end_preview().await
+            });
+            if let Err(e) = ended.await {
+                warn!("end preview error: {e}");
+            }
+        });
+
+        Ok(Self {
+            frame: Arc::new(frame),
+        })
+    }
+
+    /// Plays a scene from the host by rendering it frame by frame,
+    /// until the session is dropped.
+    pub fn play(led: Led, scene: Scene, gamma: f64) -> Result<Self> {
+        let preview = Self::start(led, scene.color_at(0.0).corrected(gamma))?;
+        let frame = Arc::downgrade(&preview.frame);
+
+        tauri::async_runtime::spawn(async move {
+            let start = Instant::now();
+            let mut ticks = interval(FRAME_INTERVAL);
+            loop {
+                ticks.tick().await;
+                let Some(frame) = frame.upgrade() else {
+                    break;
+                };
+                // scenes with a start follow the wall clock, so they stay in step with lamps
+                // playing the same scene; the first color is shown until the start
+                let time = match scene.start_at {
+                    Some(start_at) => {
+                        ((Utc::now() - start_at).num_milliseconds() as f64 / 1000.0).max(0.0)
+                    }
+                    None => start.elapsed().as_secs_f64(),
+                };
+                // unchanged frames are skipped by the writer but keep the session alive
+                if frame.send(scene.color_at(time).corrected(gamma)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(preview)
+    }
+
+    /// Replaces the pending frame, returns false when the session has already ended.
+    pub fn send(&self, rgb: [u8; 3]) -> bool {
+        self.frame.send(rgb).is_ok()
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::Serialize;
+use tokio::sync::{oneshot, Notify};
+use tokio::time::{sleep, timeout};
+use tracing::warn;
+
+/// Pause after every coalescing job, requests arriving meanwhile are merged
+/// so a lamp never gets more writes than its link can take.
+const WRITE_GAP: Duration = Duration::from_millis(150);
+/// Longest a job may run, a write the lamp never acknowledges would block its queue.
+const JOB_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A queued operation, it reports its result through a channel of its own.
+type Job = BoxFuture<'static, ()>;
+
+/// How a coalescing request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteStatus {
+    Applied,
+    /// A newer request of the same kind replaced it before it ran.
+    Superseded,
+}
+
+struct Entry {
+    /// Jobs sharing a key replace each other while waiting.
+    key: Option<&'static str>,
+    job: Job,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: Mutex<VecDeque<Entry>>,
+    /// A job is running right now.
+    running: AtomicBool,
+    closed: AtomicBool,
+    notify: Notify,
+}
+
+impl Inner {
+    fn entries(&self) -> std::sync::MutexGuard<'_, VecDeque<Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The jobs of one lamp, run one after the other by a task of their own.
+///
+/// Dropping the queue lets the task finish the jobs already queued and exit.
+pub struct JobQueue(Arc<Inner>);
+
+impl Debug for JobQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobQueue")
+            .field("pending", &self.0.entries().len())
+            .finish()
+    }
+}
+
+impl JobQueue {
+    pub fn spawn() -> Self {
+        let inner = Arc::new(Inner::default());
+        tauri::async_runtime::spawn(work(inner.clone()));
+        Self(inner)
+    }
+
+    /// Appends a job, a waiting job with the same key is dropped without running.
+    ///
+    /// The receiver gets the output of the job, or an error when it timed out or
+    /// panicked. It is closed when the job is dropped without running.
+    fn push<T, Fut>(
+        &self,
+        key: Option<&'static str>,
+        job: Fut,
+    ) -> oneshot::Receiver<anyhow::Result<T>>
+    where
+        T: Send + 'static,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job = Box::pin(async move {
+            let _ = sender.send(guard(job).await);
+        });
+        let mut entries = self.0.entries();
+        if key.is_some() {
+            entries.retain(|entry| entry.key != key);
+        }
+        entries.push_back(Entry { key, job });
+        self.0.notify.notify_one();
+        receiver
+    }
+
+    /// Queues `job` behind the ones already queued and waits for its output.
+    pub async fn run<T, E, Fut>(&self, job: Fut) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+        T: Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        let receiver = self.push(None, job);
+        receiver.await.map_err(|_| anyhow!("led queue closed"))??
+    }
+
+    /// Like `run`, but a newer job with the same `key` replaces this one while it is
+    /// still waiting.
+    pub async fn run_latest<E, Fut>(&self, key: &'static str, job: Fut) -> Result<WriteStatus, E>
+    where
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        let receiver = self.push(Some(key), job);
+        // a replaced job is dropped along with its sender
+        match receiver.await {
+            Ok(result) => result?.map(|()| WriteStatus::Applied),
+            Err(_) => Ok(WriteStatus::Superseded),
+        }
+    }
+
+    /// Whether a job is running or waiting.
+    pub fn is_busy(&self) -> bool {
+        self.0.running.load(Ordering::Acquire) || !self.0.entries().is_empty()
+    }
+}
+
+impl Drop for JobQueue {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+        self.0.notify.notify_one();
+    }
+}
+
+/// Runs a job so that neither a hang nor a panic takes the queue down with it.
+async fn guard<T>(job: impl Future<Output = T>) -> anyhow::Result<T> {
+    match timeout(JOB_TIMEOUT, AssertUnwindSafe(job).catch_unwind()).await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(_)) => {
+            warn!("led job panicked");
+            Err(anyhow!("led job panicked"))
+        }
+        Err(_) => {
+            warn!("led job timed out");
+            Err(anyhow!(
+                "led job timed out after {}s",
+                JOB_TIMEOUT.as_secs()
+            ))
+        }
+    }
+}
+
+/// Every job is guarded by `push`, so the loop keeps going whatever a job does.
+async fn work(inner: Arc<Inner>) {
+    loop {
+        let entry = loop {
+            if let Some(entry) = inner.entries().pop_front() {
+                inner.running.store(true, Ordering::Release);
+                break entry;
+            }
+            if inner.closed.load(Ordering::Acquire) {
+                return;
+            }
+            inner.notify.notified().await;
+        };
+        entry.job.await;
+        if entry.key.is_some() {
+            sleep(WRITE_GAP).await;
+        }
+        inner.running.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn record(log: Arc<Mutex<Vec<u32>>>, value: u32) -> anyhow::Result<()> {
+        log.lock().unwrap().push(value);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jobs_run_in_order() {
+        let queue = JobQueue::spawn();
+        let log = Arc::new(Mutex::new(vec![]));
+        let (first, second) = tokio::join!(
+            queue.run(record(log.clone(), 1)),
+            queue.run(async { anyhow::Ok(2) }),
+        );
+        first.unwrap();
+        assert_eq!(second.unwrap(), 2);
+        queue.run(record(log.clone(), 3)).await.unwrap();
+        assert_eq!(*log.lock().unwrap(), [1, 3]);
+        assert!(!queue.is_busy());
+    }
+
+    #[tokio::test]
+    async fn waiting_jobs_with_the_same_key_are_superseded() {
+        let queue = JobQueue::spawn();
+        let log = Arc::new(Mutex::new(vec![]));
+        let (open, gate) = oneshot::channel::<()>();
+        let blocked = queue.push(None, gate);
+        assert!(queue.is_busy());
+
+        let (first, second, other, ()) = tokio::join!(
+            queue.run_latest("color", record(log.clone(), 1)),
+            queue.run_latest("color", record(log.clone(), 2)),
+            queue.run_latest("scene", record(log.clone(), 3)),
+            async {
+                tokio::task::yield_now().await;
+                open.send(()).unwrap();
+            },
+        );
+        blocked.await.unwrap().unwrap().unwrap();
+        assert_eq!(first.unwrap(), WriteStatus::Superseded);
+        assert_eq!(second.unwrap(), WriteStatus::Applied);
+        assert_eq!(other.unwrap(), WriteStatus::Applied);
+        assert_eq!(*log.lock().unwrap(), [2, 3]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_jobs_time_out() {
+        let started = tokio::time::Instant::now();
+        let error = guard(std::future::pending::<()>()).await.unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+        assert_eq!(started.elapsed(), JOB_TIMEOUT);
+    }
+
+    async fn explode() -> anyhow::Result<()> {
+        panic!("job failed")
+    }
+
+    #[tokio::test]
+    async fn the_queue_outlives_a_panicking_job() {
+        let queue = JobQueue::spawn();
+        let error = queue.run(explode()).await.unwrap_err();
+        assert!(error.to_string().contains("panicked"));
+        assert_eq!(queue.run(async { anyhow::Ok(1) }).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::api::{Central, CentralEvent, Peripheral as _};
+use btleplug::platform::PeripheralId;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::{interval, sleep, timeout};
+use tracing::{info, warn};
+
+use crate::error::Result;
+use crate::led::Led;
+use crate::state::{connected_led, AppState};
+use crate::store;
+
+const RECONNECT_FILE: &str = "reconnect.json";
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+/// How long one attempt may take to connect and restore the lamp, a lamp that went
+/// half away can leave the connect hanging.
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(20);
+/// How often the watcher checks whether it listens to the current adapter.
+const ADAPTER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often a paused reconnect checks whether the radio is back on.
+const RADIO_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a lamp is brought back after an unexpected disconnect, lamps without one are
+/// reconnected with the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconnectPolicy {
+    pub id: PeripheralId,
+    #[serde(default = "store::default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+impl ReconnectPolicy {
+    fn load(app: &AppHandle, id: &PeripheralId) -> Result<Self> {
+        let policies: Vec<ReconnectPolicy> = store::load(app, RECONNECT_FILE)?;
+        Ok(policies
+            .into_iter()
+            .find(|policy| &policy.id == id)
+            .unwrap_or(ReconnectPolicy {
+                id: id.clone(),
+                enabled: true,
+                max_attempts: DEFAULT_MAX_ATTEMPTS,
+            }))
+    }
+}
+
+/// Published as `connection-{id}` while a lamp is being brought back.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ConnectionEvent {
+    #[serde(rename_all = "camelCase")]
+    Reconnecting {
+        attempt: u32,
+        max_attempts: u32,
+        delay: u64,
+    },
+    Reconnected,
+    /// The radio is off, attempts go on once it is back.
+    Paused,
+    /// Every attempt failed, the lamp has to be connected by hand.
+    Failed,
+}
+
+/// Whether attempts can be made, also true when the state is gone so waiting ends.
+async fn radio_on(app: &AppHandle) -> bool {
+    let Some(state) = app.try_state::<AppState>() else {
+        return true;
+    };
+    let Ok(ble_state) = state.lock().await else {
+        return true;
+    };
+    ble_state.radio_on()
+}
+
+/// Reconnects with exponential backoff, then restores the subscriptions and the clock.
+async fn reconnect(app: &AppHandle, id: &PeripheralId) -> Result<()> {
+    let policy = ReconnectPolicy::load(app, id)?;
+    if !policy.enabled {
+        return Ok(());
+    }
+    let event = format!("connection-{id}");
+    let mut delay = FIRST_DELAY;
+    for attempt in 1..=policy.max_attempts {
+        app.emit(
+            &event,
+            ConnectionEvent::Reconnecting {
+                attempt,
+                max_attempts: policy.max_attempts,
+                delay: delay.as_secs(),
+            },
+        )?;
+        sleep(delay).await;
+        delay = (delay * 2).min(MAX_DELAY);
+        if !radio_on(app).await {
+            app.emit(&event, ConnectionEvent::Paused)?;
+            while !radio_on(app).await {
+                sleep(RADIO_CHECK_INTERVAL).await;
+            }
+        }
+
+        // disconnected by hand or dropped with the adapter meanwhile
+        let Some(led) = connected_led(app, id).await else {
+            return Ok(());
+        };
+        // timed out in the job, so a hanging attempt does not hold up the lamp queue
+        let restored = led.run(|led| async move {
+            let restore = async {
+                if !led.peripheral.is_connected().await? {
+                    led.peripheral.connect().await?;
+                }
+                led.invalidate();
+                led.subscribe().await?;
+                led.set_time().await
+            };
+            timeout(RESTORE_TIMEOUT, restore)
+                .await
+                .map_err(|_| anyhow!("timed out after {}s", RESTORE_TIMEOUT.as_secs()))?
+        });
+        match restored.await {
+            Ok(()) => {
+                info!("reconnected {id} after {attempt} attempts");
+                app.emit(&event, ConnectionEvent::Reconnected)?;
+                return Ok(());
+            }
+            Err(e) => warn!("reconnect {id} attempt {attempt} failed: {e}"),
+        }
+    }
+    app.emit(&event, ConnectionEvent::Failed)?;
+    Ok(())
+}
+
+/// Starts bringing a lamp back unless it is already being reconnected.
+fn spawn_reconnect(app: &AppHandle, id: PeripheralId) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let Some(state) = app.try_state::<AppState>() else {
+            return;
+        };
+        let started = match state.lock().await {
+            Ok(mut ble_state) => ble_state.reconnecting.insert(id.clone()),
+            Err(_) => false,
+        };
+        if !started {
+            return;
+        }
+        if let Err(e) = reconnect(&app, &id).await {
+            warn!("reconnect {id} error: {e}");
+        }
+        if let Ok(mut ble_state) = state.lock().await {
+            ble_state.reconnecting.remove(&id);
+        };
+    });
+}
+
+/// Brings back the lamps that lost their link while the radio was off,
+/// platforms not reporting their disconnects are covered too.
+pub async fn resume(app: &AppHandle, leds: Vec<Led>) {
+    for led in leds {
+        if !led.peripheral.is_connected().await.unwrap_or(false) {
+            spawn_reconnect(app, led.peripheral.id());
+        }
+    }
+}
+
+/// Listens to the adapter in use until it is replaced, reconnecting lamps that drop.
+async fn listen(app: &AppHandle) -> Result<()> {
+    let Some(state) = app.try_state::<AppState>() else {
+        return Ok(());
+    };
+    let (adapter, name) = {
+        let Ok(ble_state) = state.lock().await else {
+            return Ok(());
+        };
+        (ble_state.adapter.clone(), ble_state.adapter_name.clone())
+    };
+    if name.is_none() {
+        return Ok(());
+    }
+    let mut events = adapter.events().await?;
+    let mut check = interval(ADAPTER_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(CentralEvent::DeviceDisconnected(id)) => {
+                    if state.lock().await?.leds.contains_key(&id) {
+                        warn!("led {id} disconnected");
+                        spawn_reconnect(app, id);
+                    }
+                }
+                Some(_) => {}
+                None => return Ok(()),
+            },
+            _ = check.tick() => {
+                if state.lock().await?.adapter_name != name {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Watches for lamps dropping their connection for as long as the app runs.
+pub fn watch(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = listen(&app).await {
+                warn!("reconnect watch error: {e}");
+            }
+            sleep(ADAPTER_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_reconnect_policies(app: AppHandle) -> Result<Vec<ReconnectPolicy>> {
+    store::load(&app, RECONNECT_FILE)
+}
+
+#[tauri::command]
+pub async fn set_reconnect_policy(app: AppHandle, policy: ReconnectPolicy) -> Result<()> {
+    if policy.max_attempts == 0 && policy.enabled {
+        return Err(anyhow!("at least one reconnect attempt is needed").into());
+    }
+    store::update(
+        &app,
+        RECONNECT_FILE,
+        |policies: &mut Vec<ReconnectPolicy>| {
+            match policies.iter_mut().find(|item| item.id == policy.id) {
+                Some(item) => *item = policy,
+                None => policies.push(policy),
+            }
+            Ok(())
+        },
+    )
+    .await
+}
@@ -0,0 +1,250 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::platform::PeripheralId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::ble::connect_led;
+use crate::dialog;
+use crate::error::Result;
+use crate::scan::ScanSession;
+use crate::state::{AppState, BleState};
+use crate::store;
+
+const REGISTRY_FILE: &str = "devices.json";
+/// Rounds of connection attempts made for auto-connect lamps after `init`.
+const AUTO_CONNECT_ROUNDS: u32 = 3;
+/// Scan time before each round, lamps have to be discovered before they can be connected.
+const AUTO_CONNECT_SCAN: Duration = Duration::from_secs(5);
+
+/// Counts the auto-connect runs, bumped by every `init` so only the latest one goes on.
+static AUTO_CONNECT_RUN: AtomicU64 = AtomicU64::new(0);
+
+/// A lamp the user has added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredDevice {
+    pub id: PeripheralId,
+    /// The advertised name when the lamp was added.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub room: Option<String>,
+    /// Last time the lamp was connected or disconnected.
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Connected in the background by `init`.
+    #[serde(default = "store::default_true")]
+    pub auto_connect: bool,
+}
+
+fn load(app: &AppHandle) -> Result<Vec<RegisteredDevice>> {
+    store::load(app, REGISTRY_FILE)
+}
+
+/// Updates the last seen time of a registered lamp, unknown lamps are ignored.
+pub async fn touch(app: &AppHandle, id: &PeripheralId) -> Result<()> {
+    store::update(app, REGISTRY_FILE, |devices: &mut Vec<RegisteredDevice>| {
+        if let Some(device) = devices.iter_mut().find(|device| &device.id == id) {
+            device.last_seen = Some(Utc::now());
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Connects the auto-connect lamps in the background, the run of a previous `init` stops.
+pub fn auto_connect(app: AppHandle) {
+    let run = AUTO_CONNECT_RUN.fetch_add(1, Ordering::SeqCst) + 1;
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = connect_registered(&app, run).await {
+            warn!("auto connect error: {e}");
+        }
+    });
+}
+
+/// Scans before every round so lamps that are not known to the adapter yet can be found,
+/// relying on the scan of the user when one is running. Gives up once another `init`
+/// has started a newer `run`.
+async fn connect_registered(app: &AppHandle, run: u64) -> Result<()> {
+    let superseded = || AUTO_CONNECT_RUN.load(Ordering::SeqCst) != run;
+    let mut pending = load(app)?
+        .into_iter()
+        .filter(|device| device.auto_connect)
+        .map(|device| device.id)
+        .collect::<Vec<_>>();
+    let state = app.state::<AppState>();
+
+    for _ in 0..AUTO_CONNECT_ROUNDS {
+        if pending.is_empty() || superseded() {
+            break;
+        }
+        let scan = start_scan(&state).await?;
+        sleep(AUTO_CONNECT_SCAN).await;
+        if let Some(id) = scan {
+            stop_scan(&state, id).await?;
+        }
+
+        let mut connected = vec![];
+        for id in &pending {
+            if superseded() {
+                return Ok(());
+            }
+            if state.lock().await?.leds.contains_key(id) {
+                connected.push(id.clone());
+                continue;
+            }
+            match connect_led(app, &state, id.clone()).await {
+                Ok(_) => {
+                    info!("auto connected {id}");
+                    connected.push(id.clone());
+                }
+                Err(e) => warn!("auto connect {id} failed: {e}"),
+            }
+        }
+        for id in &connected {
+            touch(app, id).await?;
+        }
+        pending.retain(|id| !connected.contains(id));
+    }
+    Ok(())
+}
+
+/// Starts a scan session for a round, unless a scan is running or waiting for the radio
+/// already. Returns the id of the session started.
+///
+/// The session is kept in the state like one started by `start_scan`, so a scan started
+/// by the user replaces it and the radio pauses it.
+async fn start_scan(state: &AppState) -> Result<Option<u32>> {
+    let idle = |ble_state: &BleState| {
+        ble_state.scan.is_none() && ble_state.paused_scan.is_none() && ble_state.radio_on()
+    };
+    let (adapter, name) = {
+        let ble_state = state.lock().await?;
+        if !idle(&ble_state) {
+            return Ok(None);
+        }
+        (ble_state.adapter.clone(), ble_state.adapter_name.clone())
+    };
+    let session = match ScanSession::start_unobserved(adapter, AUTO_CONNECT_SCAN).await {
+        Ok(session) => session,
+        Err(e) => {
+            warn!("auto connect scan failed: {e}");
+            return Ok(None);
+        }
+    };
+    let id = session.id();
+    let stale = {
+        let mut ble_state = state.lock().await?;
+        if ble_state.adapter_name == name && idle(&ble_state) {
+            ble_state.scan = Some(session);
+            None
+        } else {
+            Some(session)
+        }
+    };
+    match stale {
+        Some(session) => {
+            session.stop().await;
+            Ok(None)
+        }
+        None => Ok(Some(id)),
+    }
+}
+
+/// Ends the session of a round, unless it has been replaced meanwhile.
+async fn stop_scan(state: &AppState, id: u32) -> Result<()> {
+    let session = {
+        let mut ble_state = state.lock().await?;
+        if let Some(paused) = ble_state.paused_scan.take_if(|paused| paused.id() == id) {
+            paused.cancel();
+        }
+        ble_state.scan.take_if(|session| session.id() == id)
+    };
+    if let Some(session) = session {
+        session.stop().await;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_registry(app: AppHandle) -> Result<Vec<RegisteredDevice>> {
+    load(&app)
+}
+
+/// Adds a lamp to the registry, registering it again only refreshes its name.
+#[tauri::command]
+pub async fn register_device(app: AppHandle, id: PeripheralId, name: Option<String>) -> Result<()> {
+    store::update(
+        &app,
+        REGISTRY_FILE,
+        |devices: &mut Vec<RegisteredDevice>| {
+            match devices.iter_mut().find(|device| device.id == id) {
+                Some(device) => device.name = name.or(device.name.take()),
+                None => devices.push(RegisteredDevice {
+                    id,
+                    name,
+                    alias: None,
+                    room: None,
+                    last_seen: None,
+                    auto_connect: true,
+                }),
+            }
+            Ok(())
+        },
+    )
+    .await
+}
+
+/// Changes alias, room and auto-connect of a registered lamp.
+#[tauri::command]
+pub async fn update_device(app: AppHandle, device: RegisteredDevice) -> Result<()> {
+    store::update(
+        &app,
+        REGISTRY_FILE,
+        |devices: &mut Vec<RegisteredDevice>| {
+            let item = devices
+                .iter_mut()
+                .find(|item| item.id == device.id)
+                .ok_or(anyhow!("device {} not registered", device.id))?;
+            *item = RegisteredDevice {
+                last_seen: item.last_seen,
+                ..device
+            };
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn unregister_device(app: AppHandle, id: PeripheralId) -> Result<()> {
+    store::update(
+        &app,
+        REGISTRY_FILE,
+        |devices: &mut Vec<RegisteredDevice>| {
+            devices.retain(|device| device.id != id);
+            Ok(())
+        },
+    )
+    .await
+}
+
+/// Saves the registry as JSON, returns false when the dialog is cancelled.
+#[tauri::command]
+pub async fn export_registry(app: AppHandle) -> Result<bool> {
+    let devices = load(&app)?;
+    let Some(path) = dialog::save_file(&app, "Device Registry", &["json"], "devices.json").await?
+    else {
+        return Ok(false);
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&devices)?).map_err(anyhow::Error::from)?;
+    Ok(true)
+}
@@ -0,0 +1,189 @@
+use crate::color::Color;
+use crate::error::Result;
+use crate::scene::{Scene, SceneKind};
+
+/// Upper bound of frames returned by `render_scene`.
+const MAX_FRAMES: usize = 100_000;
+
+/// The color `progress` of the way from `from` to `to`.
+pub fn mix(from: Color, to: Color, progress: f64) -> Color {
+    let mut rgb = [0; 3];
+    for (channel, value) in rgb.iter_mut().enumerate() {
+        let (a, b) = (from.0[channel] as f64, to.0[channel] as f64);
+        *value = (a + (b - a) * progress).round().clamp(0.0, 255.0) as u8;
+    }
+    Color(rgb)
+}
+
+impl Scene {
+    /// Seconds until the scene repeats, zero for scenes that never change.
+    pub fn cycle(&self) -> f64 {
+        match &self.kind {
+            SceneKind::Solid { .. } => 0.0,
+            SceneKind::Gradient { colors, .. } => {
+                colors.iter().map(|color| color.length() as f64).sum()
+            }
+        }
+    }
+
+    /// The color shown `time` seconds after the scene started.
+    ///
+    /// Mirrors the firmware: every color is held for its `hold`, then moves to the
+    /// next one (wrapping to the first) over its duration along its curve. Without
+    /// a curve, `linear` gradients crossfade and the others step.
+    pub fn color_at(&self, time: f64) -> Color {
+        let (colors, linear) = match &self.kind {
+            SceneKind::Solid { color } => return *color,
+            SceneKind::Gradient { colors, linear } => (colors, *linear),
+        };
+        let cycle = self.cycle();
+        if cycle <= 0.0 {
+            return colors.first().map_or(Color([0; 3]), |color| color.color);
+        }
+
+        let mut time = time.rem_euclid(cycle);
+        for (index, current) in colors.iter().enumerate() {
+            if time < current.length() as f64 {
+                let progress = (time - current.hold as f64) / current.duration as f64;
+                if progress <= 0.0 {
+                    return current.color;
+                }
+                let next = &colors[(index + 1) % colors.len()];
+                return mix(
+                    current.color,
+                    next.color,
+                    current.curve(linear).ease(progress),
+                );
+            }
+            time -= current.length() as f64;
+        }
+        colors[colors.len() - 1].color
+    }
+
+    /// Samples `seconds` of the scene at `sample_rate` frames per second.
+    pub fn render(&self, sample_rate: f64, seconds: f64) -> Vec<Color> {
+        let frames = (seconds * sample_rate).ceil().max(0.0) as usize;
+        (0..frames.min(MAX_FRAMES))
+            .map(|frame| self.color_at(frame as f64 / sample_rate))
+            .collect()
+    }
+}
+
+/// Renders a scene into frames, one full cycle when `seconds` is not given.
+#[tauri::command]
+pub async fn render_scene(
+    scene: Scene,
+    sample_rate: f64,
+    seconds: Option<f64>,
+) -> Result<Vec<Color>> {
+    if sample_rate <= 0.0 {
+        return Err(anyhow::anyhow!("sample rate must be positive").into());
+    }
+    let seconds = seconds.unwrap_or_else(|| scene.cycle().max(1.0 / sample_rate));
+    Ok(scene.render(sample_rate, seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::fixtures::*;
+    use crate::scene::Curve;
+
+    const WHITE: Color = Color([255, 255, 255]);
+    /// Halfway between red and blue.
+    const PURPLE: Color = Color([128, 0, 128]);
+
+    #[test]
+    fn linear_crossfades() {
+        let scene = gradient(vec![entry(RED, 2, 0, None), entry(BLUE, 2, 0, None)], true);
+        assert_eq!(scene.cycle(), 4.0);
+        assert_eq!(
+            scene.render(1.0, 6.0),
+            [RED, PURPLE, BLUE, PURPLE, RED, PURPLE]
+        );
+    }
+
+    #[test]
+    fn step_switches_at_the_end() {
+        let scene = gradient(vec![entry(RED, 2, 0, None), entry(BLUE, 2, 0, None)], false);
+        assert_eq!(
+            scene.render(2.0, 4.0),
+            [RED, RED, RED, RED, BLUE, BLUE, BLUE, BLUE]
+        );
+
+        // an explicit step curve wins over the linear flag
+        let scene = gradient(
+            vec![entry(RED, 2, 0, Some(Curve::Step)), entry(BLUE, 2, 0, None)],
+            true,
+        );
+        assert_eq!(scene.render(1.0, 4.0), [RED, RED, BLUE, PURPLE]);
+    }
+
+    #[test]
+    fn hold_comes_before_the_transition() {
+        let scene = gradient(vec![entry(RED, 2, 2, None), entry(BLUE, 2, 0, None)], true);
+        assert_eq!(scene.cycle(), 6.0);
+        assert_eq!(
+            scene.render(1.0, 6.0),
+            [RED, RED, RED, PURPLE, BLUE, PURPLE]
+        );
+    }
+
+    #[test]
+    fn curve_eases_the_transition() {
+        let scene = gradient(
+            vec![
+                entry(RED, 4, 0, Some(Curve::EaseIn)),
+                entry(BLUE, 4, 0, Some(Curve::EaseOut)),
+            ],
+            true,
+        );
+        assert_eq!(
+            scene.render(1.0, 8.0),
+            [
+                RED,
+                Color([239, 0, 16]),
+                Color([191, 0, 64]),
+                Color([112, 0, 143]),
+                BLUE,
+                Color([112, 0, 143]),
+                Color([191, 0, 64]),
+                Color([239, 0, 16]),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_duration_entries() {
+        // a color without length is jumped to once the one before it has faded into it
+        let scene = gradient(
+            vec![
+                entry(RED, 2, 0, None),
+                entry(WHITE, 0, 0, None),
+                entry(BLUE, 2, 0, None),
+            ],
+            true,
+        );
+        assert_eq!(scene.cycle(), 4.0);
+        assert_eq!(
+            scene.render(1.0, 4.0),
+            [RED, Color([255, 128, 128]), BLUE, PURPLE]
+        );
+
+        // a hold without transition shows its color, then switches
+        let scene = gradient(vec![entry(RED, 0, 2, None), entry(BLUE, 2, 0, None)], true);
+        assert_eq!(scene.render(1.0, 4.0), [RED, RED, BLUE, PURPLE]);
+
+        // a scene that never moves shows its first color
+        let scene = gradient(vec![entry(RED, 0, 0, None), entry(BLUE, 0, 0, None)], true);
+        assert_eq!(scene.cycle(), 0.0);
+        assert_eq!(scene.render(1.0, 2.0), [RED, RED]);
+    }
+
+    #[test]
+    fn frames_are_capped() {
+        let scene = gradient(vec![entry(RED, 2, 0, None)], true);
+        assert_eq!(scene.render(1000.0, 1000.0).len(), MAX_FRAMES);
+        assert!(scene.render(1.0, -1.0).is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+use btleplug::api::{Central, CentralEvent, Peripheral, ScanFilter};
+use btleplug::platform::{Adapter, PeripheralId};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::async_runtime::JoinHandle;
+use tauri::ipc::Channel;
+use tokio::sync::oneshot;
+use tokio::time::{interval, sleep};
+use uuid::uuid;
+
+use crate::ble::Device;
+use crate::error::Result;
+
+/// Scans stop by themselves after this long unless another duration is given.
+pub const DEFAULT_SCAN_DURATION: Duration = Duration::from_secs(30);
+/// Updates of a device within this window are sent as one.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+/// Devices not heard of for this long are reported lost.
+const LOST_AFTER: Duration = Duration::from_secs(15);
+
+/// Sent over the scan channel, devices carry their RSSI and TX power.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum ScanEvent {
+    Added(Device),
+    Updated(Device),
+    Lost(PeripheralId),
+    /// The session ended, by `stop_scan`, a newer scan or its duration running out.
+    Stopped,
+    /// The radio was switched off, the session goes on for its remaining time once it is back.
+    Paused,
+    Resumed,
+}
+
+struct Seen {
+    at: Instant,
+    /// The last state sent for the device.
+    sent: Value,
+}
+
+/// A running scan, dropping it stops the scan.
+pub struct ScanSession {
+    /// Carries the event the session ends with.
+    stop: oneshot::Sender<ScanEvent>,
+    task: JoinHandle<()>,
+    channel: Channel<ScanEvent>,
+    ends_at: Instant,
+}
+
+/// A scan put on hold while the radio is off.
+pub struct PausedScan {
+    channel: Channel<ScanEvent>,
+    remaining: Duration,
+}
+
+impl Debug for ScanSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanSession")
+            .field("channel", &self.channel.id())
+            .field("ends_at", &self.ends_at)
+            .finish()
+    }
+}
+
+impl Debug for PausedScan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PausedScan")
+            .field("channel", &self.channel.id())
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+impl ScanSession {
+    pub async fn start(
+        adapter: Adapter,
+        channel: Channel<ScanEvent>,
+        duration: Duration,
+    ) -> Result<Self> {
+        let mut events = adapter.events().await?;
+        adapter
+            .start_scan(ScanFilter {
+                services: vec![uuid!("e572775c-0df9-4b44-926b-b692e31d6971")],
+            })
+            .await?;
+
+        let (stop, mut stopped) = oneshot::channel();
+        let ends_at = Instant::now() + duration;
+        let task = tauri::async_runtime::spawn({
+            let channel = channel.clone();
+            async move {
+                let deadline = sleep(duration);
+                tokio::pin!(deadline);
+                let mut flush = interval(FLUSH_INTERVAL);
+                let mut seen = HashMap::new();
+                let mut pending = HashSet::new();
+                let last = loop {
+                    tokio::select! {
+                        last = &mut stopped => break last.unwrap_or(ScanEvent::Stopped),
+                        _ = &mut deadline => break ScanEvent::Stopped,
+                        event = events.next() => match event {
+                            Some(
+                                CentralEvent::DeviceDiscovered(id)
+                                | CentralEvent::DeviceUpdated(id)
+                                | CentralEvent::ManufacturerDataAdvertisement { id, .. }
+                                | CentralEvent::ServiceDataAdvertisement { id, .. }
+                                | CentralEvent::ServicesAdvertisement { id, .. },
+                            ) => {
+                                pending.insert(id);
+                            }
+                            Some(_) => {}
+                            None => break ScanEvent::Stopped,
+                        },
+                        _ = flush.tick() => {
+                            let sent =
+                                flush_pending(&adapter, &channel, &mut seen, &mut pending).await;
+                            if sent.is_err() {
+                                break ScanEvent::Stopped;
+                            }
+                        }
+                    }
+                };
+                adapter.stop_scan().await.ok();
+                channel.send(last).ok();
+            }
+        });
+
+        Ok(Self {
+            stop,
+            task,
+            channel,
+            ends_at,
+        })
+    }
+
+    /// A scan nobody listens to, e.g. to discover lamps before connecting them.
+    pub async fn start_unobserved(adapter: Adapter, duration: Duration) -> Result<Self> {
+        Self::start(adapter, Channel::new(|_| Ok(())), duration).await
+    }
+
+    /// Tells sessions apart, a paused and resumed session keeps its id.
+    pub fn id(&self) -> u32 {
+        self.channel.id()
+    }
+
+    /// Stops the scan and waits until the adapter has stopped scanning.
+    pub async fn stop(self) {
+        self.end(ScanEvent::Stopped).await;
+    }
+
+    /// Stops the scan, keeping what is needed to go on with it later.
+    pub async fn pause(self) -> PausedScan {
+        let paused = PausedScan {
+            channel: self.channel.clone(),
+            remaining: self.ends_at.saturating_duration_since(Instant::now()),
+        };
+        self.end(ScanEvent::Paused).await;
+        paused
+    }
+
+    async fn end(self, last: ScanEvent) {
+        let Self { stop, task, .. } = self;
+        stop.send(last).ok();
+        task.await.ok();
+    }
+}
+
+impl PausedScan {
+    /// A scan started while the radio is off, it is reported paused right away.
+    pub fn new(channel: Channel<ScanEvent>, duration: Duration) -> Self {
+        channel.send(ScanEvent::Paused).ok();
+        Self {
+            channel,
+            remaining: duration,
+        }
+    }
+
+    /// Starts scanning again for the time that was left, the paused scan is kept on failure.
+    pub async fn resume(&self, adapter: Adapter) -> Result<ScanSession> {
+        let session = ScanSession::start(adapter, self.channel.clone(), self.remaining).await?;
+        self.channel.send(ScanEvent::Resumed).ok();
+        Ok(session)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.channel.id()
+    }
+
+    /// Gives up on the scan, e.g. when `stop_scan` is called while the radio is off.
+    pub fn cancel(self) {
+        self.channel.send(ScanEvent::Stopped).ok();
+    }
+}
+
+/// Sends the devices heard of since the last flush when they are new or changed,
+/// and reports those gone quiet as lost.
+async fn flush_pending(
+    adapter: &Adapter,
+    channel: &Channel<ScanEvent>,
+    seen: &mut HashMap<PeripheralId, Seen>,
+    pending: &mut HashSet<PeripheralId>,
+) -> Result<()> {
+    let now = Instant::now();
+    for id in pending.drain() {
+        let Ok(peripheral) = adapter.peripheral(&id).await else {
+            continue;
+        };
+        let Ok(Some(properties)) = peripheral.properties().await else {
+            continue;
+        };
+        let device = Device { id, properties };
+        let value = serde_json::to_value(&device)?;
+        match seen.get_mut(&device.id) {
+            Some(item) => {
+                item.at = now;
+                if item.sent != value {
+                    item.sent = value;
+                    channel.send(ScanEvent::Updated(device))?;
+                }
+            }
+            None => {
+                seen.insert(
+                    device.id.clone(),
+                    Seen {
+                        at: now,
+                        sent: value,
+                    },
+                );
+                channel.send(ScanEvent::Added(device))?;
+            }
+        }
+    }
+
+    let lost = seen
+        .iter()
+        .filter(|(_, item)| now.duration_since(item.at) > LOST_AFTER)
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+    for id in lost {
+        seen.remove(&id);
+        channel.send(ScanEvent::Lost(id))?;
+    }
+    Ok(())
+}
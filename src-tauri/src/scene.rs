@@ -0,0 +1,362 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::color::Color;
+use crate::led::Features;
+use crate::render::mix;
+
+/// How a gradient moves from one color to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Curve {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// Keeps the color and switches at the end of the transition.
+    Step,
+}
+
+impl Curve {
+    /// Maps the linear progress of a transition to the eased one.
+    pub fn ease(&self, progress: f64) -> f64 {
+        let p = progress.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => p,
+            Curve::EaseIn => p * p,
+            Curve::EaseOut => 1.0 - (1.0 - p) * (1.0 - p),
+            Curve::EaseInOut if p < 0.5 => 2.0 * p * p,
+            Curve::EaseInOut => 1.0 - (-2.0 * p + 2.0).powi(2) / 2.0,
+            Curve::Step => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorDuration {
+    pub color: Color,
+    /// Seconds of the transition to the next color.
+    pub duration: u32,
+    /// Transition curve, follows the `linear` flag of the scene when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<Curve>,
+    /// Seconds the color is held before the transition starts.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub hold: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+impl ColorDuration {
+    pub fn new(color: Color, duration: u32) -> Self {
+        Self {
+            color,
+            duration,
+            curve: None,
+            hold: 0,
+        }
+    }
+
+    pub fn curve(&self, linear: bool) -> Curve {
+        self.curve
+            .unwrap_or(if linear { Curve::Linear } else { Curve::Step })
+    }
+
+    /// Seconds from the start of this color to the start of the next one.
+    pub fn length(&self) -> u32 {
+        self.hold + self.duration
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SceneKind {
+    Solid {
+        color: Color,
+    },
+    Gradient {
+        colors: Vec<ColorDuration>,
+        linear: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scene {
+    pub name: String,
+    #[serde(default)]
+    pub auto_on: bool,
+    /// When the first color starts, by the lamp clock. Lamps given the same start play
+    /// in step, without it a lamp starts once the scene has been written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub kind: SceneKind,
+}
+
+/// Scene names longer than this are rejected, same as in the scene editor.
+pub const MAX_NAME_LEN: usize = 20;
+
+impl Scene {
+    /// Checks what the scene editor enforces, for scenes coming from elsewhere.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("scene name is empty");
+        }
+        if self.name.chars().count() > MAX_NAME_LEN {
+            bail!(
+                "scene name {} is longer than {MAX_NAME_LEN} characters",
+                self.name
+            );
+        }
+        if let SceneKind::Gradient { colors, .. } = &self.kind {
+            if colors.is_empty() {
+                bail!("gradient scene {} has no colors", self.name);
+            }
+            if colors.iter().all(|color| color.length() == 0) {
+                bail!("gradient scene {} has zero duration", self.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pieces an eased transition is cut into for firmware that only crossfades linearly.
+const CURVE_SEGMENTS: u32 = 8;
+
+/// Adds an entry to the legacy wire form, which has no zero length entries.
+fn push_entry(entries: &mut Vec<Value>, rgb: [u8; 3], duration: u32) {
+    if duration > 0 {
+        entries.push(json!({ "color": rgb, "duration": duration }));
+    }
+}
+
+/// A jump to the next entry, made from the last second of `duration` where the
+/// firmware cannot jump.
+fn push_jump(entries: &mut Vec<Value>, rgb: [u8; 3], duration: u32) {
+    push_entry(entries, rgb, duration.saturating_sub(1));
+    push_entry(entries, rgb, duration.min(1));
+}
+
+impl Scene {
+    /// The JSON written to the scene characteristic, with colors gamma corrected
+    /// into the `[r, g, b]` arrays the firmware reads.
+    ///
+    /// Firmware with the `curves` feature gets holds and curves as they are. For the
+    /// others they are played on plain `linear` entries: holds fade into the same color,
+    /// steps jump over the last second and eased transitions are cut into linear pieces.
+    pub fn to_wire(&self, gamma: f64, features: Features) -> Value {
+        let correct = |color: Color| color.corrected(gamma);
+        let kind = match &self.kind {
+            SceneKind::Solid { color } => {
+                json!({ "type": "solid", "color": correct(*color) })
+            }
+            SceneKind::Gradient { colors, linear } if features.curves => {
+                let entries = colors
+                    .iter()
+                    .map(|color| {
+                        let mut entry =
+                            json!({ "color": correct(color.color), "duration": color.duration });
+                        if color.hold > 0 {
+                            entry["hold"] = color.hold.into();
+                        }
+                        if let Some(curve) = color.curve {
+                            entry["curve"] = json!(curve);
+                        }
+                        entry
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "type": "gradient", "colors": entries, "linear": linear })
+            }
+            SceneKind::Gradient { colors, linear } => {
+                let crossfade = colors
+                    .iter()
+                    .any(|color| color.curve(*linear) != Curve::Step);
+                let mut entries = vec![];
+                for (index, color) in colors.iter().enumerate() {
+                    let rgb = correct(color.color);
+                    if !crossfade {
+                        push_entry(&mut entries, rgb, color.length());
+                        continue;
+                    }
+                    match color.curve(*linear) {
+                        Curve::Step => push_jump(&mut entries, rgb, color.length()),
+                        // without a transition the hold ends in a jump
+                        _ if color.duration == 0 => push_jump(&mut entries, rgb, color.hold),
+                        Curve::Linear => {
+                            push_entry(&mut entries, rgb, color.hold);
+                            push_entry(&mut entries, rgb, color.duration);
+                        }
+                        curve => {
+                            push_entry(&mut entries, rgb, color.hold);
+                            let next = colors[(index + 1) % colors.len()].color;
+                            let pieces = color.duration.min(CURVE_SEGMENTS);
+                            let at = |piece: u32| {
+                                (piece as f64 * color.duration as f64 / pieces as f64).round()
+                                    as u32
+                            };
+                            for piece in 0..pieces {
+                                let (start, end) = (at(piece), at(piece + 1));
+                                let progress = start as f64 / color.duration as f64;
+                                let from = mix(color.color, next, curve.ease(progress));
+                                push_entry(&mut entries, correct(from), end - start);
+                            }
+                        }
+                    }
+                }
+                json!({ "type": "gradient", "colors": entries, "linear": crossfade })
+            }
+        };
+
+        let mut wire = json!({ "name": self.name, "autoOn": self.auto_on });
+        if let Some(start_at) = self.start_at.filter(|_| features.start_at) {
+            // milliseconds, the same as the time written by `set_time`
+            wire["startAt"] = start_at.timestamp_millis().into();
+        }
+        if let (Value::Object(wire), Value::Object(kind)) = (&mut wire, kind) {
+            wire.extend(kind);
+        }
+        wire
+    }
+}
+
+/// Scene builders shared by the tests of the modules working on scenes.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    pub const RED: Color = Color([255, 0, 0]);
+    pub const BLUE: Color = Color([0, 0, 255]);
+
+    pub fn entry(color: Color, duration: u32, hold: u32, curve: Option<Curve>) -> ColorDuration {
+        ColorDuration {
+            color,
+            duration,
+            curve,
+            hold,
+        }
+    }
+
+    pub fn gradient(colors: Vec<ColorDuration>, linear: bool) -> Scene {
+        Scene {
+            name: "test".into(),
+            auto_on: false,
+            start_at: None,
+            kind: SceneKind::Gradient { colors, linear },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::*;
+
+    fn colors(wire: &Value) -> Vec<([u8; 3], u64)> {
+        wire["colors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    serde_json::from_value(entry["color"].clone()).unwrap(),
+                    entry["duration"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    const CURVES: Features = Features {
+        preview: false,
+        curves: true,
+        start_at: true,
+    };
+
+    #[test]
+    fn colors_are_corrected_arrays() {
+        let scene = Scene {
+            kind: SceneKind::Solid {
+                color: Color([255, 128, 0]),
+            },
+            ..gradient(vec![], true)
+        };
+        let wire = scene.to_wire(1.0, Features::default());
+        assert_eq!(wire["color"], json!([255, 128, 0]));
+        assert_eq!(wire["type"], "solid");
+        let corrected = Color([255, 128, 0]).corrected(2.2);
+        assert_ne!(corrected, [255, 128, 0]);
+        assert_eq!(scene.to_wire(2.2, CURVES)["color"], json!(corrected));
+    }
+
+    #[test]
+    fn curves_are_sent_to_firmware_knowing_them() {
+        let scene = gradient(
+            vec![
+                entry(RED, 4, 2, Some(Curve::EaseIn)),
+                entry(BLUE, 2, 0, None),
+            ],
+            true,
+        );
+        let wire = scene.to_wire(1.0, CURVES);
+        assert_eq!(wire["colors"][0]["curve"], "easeIn");
+        assert_eq!(wire["colors"][0]["hold"], 2);
+        assert_eq!(colors(&wire), [(RED.0, 4), (BLUE.0, 2)]);
+    }
+
+    #[test]
+    fn legacy_firmware_gets_linear_entries() {
+        let scene = gradient(
+            vec![entry(RED, 2, 3, None), entry(BLUE, 2, 0, Some(Curve::Step))],
+            true,
+        );
+        let wire = scene.to_wire(1.0, Features::default());
+        assert_eq!(wire["linear"], true);
+        assert_eq!(
+            colors(&wire),
+            [(RED.0, 3), (RED.0, 2), (BLUE.0, 1), (BLUE.0, 1)]
+        );
+        assert!(wire["colors"][0].get("curve").is_none());
+    }
+
+    #[test]
+    fn legacy_firmware_gets_curves_cut_into_pieces() {
+        let scene = gradient(
+            vec![
+                entry(RED, 4, 0, Some(Curve::EaseIn)),
+                entry(BLUE, 2, 0, None),
+            ],
+            true,
+        );
+        let wire = scene.to_wire(1.0, Features::default());
+        // the pieces start on the eased colors rendered at their start
+        assert_eq!(
+            colors(&wire),
+            [
+                (RED.0, 1),
+                ([239, 0, 16], 1),
+                ([191, 0, 64], 1),
+                ([112, 0, 143], 1),
+                (BLUE.0, 2)
+            ]
+        );
+        let total: u64 = colors(&wire).iter().map(|(_, duration)| duration).sum();
+        assert_eq!(total as f64, scene.cycle());
+    }
+
+    #[test]
+    fn start_needs_the_feature() {
+        let scene = Scene {
+            start_at: Some(DateTime::from_timestamp_millis(1_000).unwrap()),
+            ..gradient(vec![entry(RED, 2, 0, None)], true)
+        };
+        assert!(scene
+            .to_wire(1.0, Features::default())
+            .get("startAt")
+            .is_none());
+        assert_eq!(scene.to_wire(1.0, CURVES)["startAt"], 1_000);
+    }
+}
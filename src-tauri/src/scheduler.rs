@@ -0,0 +1,580 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::platform::PeripheralId;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+use crate::ble::{control_led, set_led_scene};
+use crate::error::Result;
+use crate::led::LedCommand;
+use crate::scene::Scene;
+use crate::settings::Settings;
+use crate::state::AppState;
+use crate::store;
+
+const SCHEDULE_FILE: &str = "schedule.json";
+const HISTORY_FILE: &str = "schedule_history.json";
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+/// Only the newest records are kept.
+const HISTORY_LIMIT: usize = 500;
+/// Runs later than this after their scheduled time are recorded as catch-ups.
+const CATCH_UP_GRACE: TimeDelta = TimeDelta::minutes(2);
+/// How far back missed occurrences are looked for.
+const LOOKBACK_DAYS: u64 = 366;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Recurrence {
+    Once {
+        at: DateTime<Utc>,
+    },
+    /// Every `interval` days counted from `start`.
+    Interval {
+        start: NaiveDate,
+        interval: u32,
+        time: NaiveTime,
+    },
+    Weekdays {
+        /// 1 is Monday, 7 is Sunday.
+        days: Vec<u32>,
+        time: NaiveTime,
+        #[serde(default, rename = "skipHolidays")]
+        skip_holidays: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum Action {
+    Control(LedCommand),
+    Scene(Scene),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleRule {
+    pub name: String,
+    pub devices: Vec<PeripheralId>,
+    pub recurrence: Recurrence,
+    pub action: Action,
+    #[serde(default = "store::default_true")]
+    pub enabled: bool,
+    /// Run the newest occurrence missed while the machine was asleep or the app closed.
+    #[serde(default = "store::default_true")]
+    pub catch_up: bool,
+    /// Occurrences up to this time have been handled.
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub rules: Vec<ScheduleRule>,
+    /// Dates skipped by rules with `skipHolidays`.
+    pub holidays: Vec<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Execution {
+    pub rule: String,
+    pub id: PeripheralId,
+    pub scheduled: DateTime<Utc>,
+    pub executed: DateTime<Utc>,
+    pub catch_up: bool,
+    pub error: Option<String>,
+}
+
+impl Recurrence {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Recurrence::Once { .. } => {}
+            Recurrence::Interval { interval, .. } => {
+                if *interval == 0 {
+                    return Err(anyhow!("interval must be at least one day").into());
+                }
+            }
+            Recurrence::Weekdays { days, .. } => {
+                if days.is_empty() {
+                    return Err(anyhow!("at least one weekday is needed").into());
+                }
+                if let Some(day) = days.iter().find(|day| !(1..=7).contains(*day)) {
+                    return Err(
+                        anyhow!("invalid weekday {day}, 1 is Monday and 7 is Sunday").into(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn occurs_on(&self, date: NaiveDate, holidays: &[NaiveDate]) -> Option<NaiveTime> {
+        match self {
+            Recurrence::Once { .. } => None,
+            Recurrence::Interval {
+                start,
+                interval,
+                time,
+            } => {
+                let days = (date - *start).num_days();
+                (days >= 0 && days % (*interval).max(1) as i64 == 0).then_some(*time)
+            }
+            Recurrence::Weekdays {
+                days,
+                time,
+                skip_holidays,
+            } => (days.contains(&date.weekday().number_from_monday())
+                && !(*skip_holidays && holidays.contains(&date)))
+            .then_some(*time),
+        }
+    }
+
+    /// The newest occurrence in `(since, now]`, with days and times read in `tz`.
+    ///
+    /// A time skipped when the clocks go forward runs an hour later,
+    /// a time repeated when they go back runs the first time.
+    pub fn latest_between<Tz: TimeZone>(
+        &self,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+        holidays: &[NaiveDate],
+        tz: &Tz,
+    ) -> Option<DateTime<Utc>> {
+        if let Recurrence::Once { at } = self {
+            return (*at > since && *at <= now).then_some(*at);
+        }
+        let today = now.with_timezone(tz).date_naive();
+        let first = since.with_timezone(tz).date_naive();
+        (0..LOOKBACK_DAYS)
+            .filter_map(|days| today.checked_sub_days(Days::new(days)))
+            .take_while(|date| *date >= first)
+            .filter_map(|date| {
+                let at = date.and_time(self.occurs_on(date, holidays)?);
+                let at = tz.from_local_datetime(&at).earliest().or_else(|| {
+                    tz.from_local_datetime(&(at + TimeDelta::hours(1)))
+                        .earliest()
+                })?;
+                Some(at.with_timezone(&Utc))
+            })
+            .find(|at| *at > since && *at <= now)
+    }
+}
+
+async fn execute(app: &AppHandle, id: &PeripheralId, action: &Action) -> Result<()> {
+    let state = app.state::<AppState>();
+    match action {
+        Action::Control(command) => control_led(&state, id, command.clone()).await?,
+        // like a scene picked by hand, it ends the running playlist
+        Action::Scene(scene) => {
+            set_led_scene(&state, id, scene, Settings::load(app)?.gamma).await?
+        }
+    };
+    Ok(())
+}
+
+/// A rule occurrence to run, taken from the schedule so the lamps are written
+/// without holding the schedule lock.
+struct Due {
+    rule: String,
+    devices: Vec<PeripheralId>,
+    action: Action,
+    scheduled: DateTime<Utc>,
+    catch_up: bool,
+}
+
+/// Returns the occurrences that became due and marks every other rule as run up to now.
+///
+/// Rules with a due occurrence keep their `last_run` until all their lamps ran it,
+/// so a lamp that was away is caught up on a later tick.
+async fn take_due(app: &AppHandle, now: DateTime<Utc>) -> Result<Vec<Due>> {
+    let _guard = store::lock(SCHEDULE_FILE).await;
+    let mut schedule: Schedule = store::load(app, SCHEDULE_FILE)?;
+    if schedule.rules.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut due = vec![];
+    for rule in schedule.rules.iter_mut() {
+        let since = rule.last_run.unwrap_or(now);
+        let scheduled = rule
+            .recurrence
+            .latest_between(since, now, &schedule.holidays, &Local)
+            .filter(|_| rule.enabled);
+        let catch_up = scheduled.is_some_and(|scheduled| now - scheduled > CATCH_UP_GRACE);
+        match scheduled {
+            Some(scheduled) if rule.catch_up || !catch_up => due.push(Due {
+                rule: rule.name.clone(),
+                devices: rule.devices.clone(),
+                action: rule.action.clone(),
+                scheduled,
+                catch_up,
+            }),
+            _ => rule.last_run = Some(now),
+        }
+    }
+    store::save(app, SCHEDULE_FILE, &schedule)?;
+    Ok(due)
+}
+
+/// Runs every rule that became due since its last run.
+pub async fn tick(app: &AppHandle) -> Result<()> {
+    let now = Utc::now();
+    let due = take_due(app, now).await?;
+    if due.is_empty() {
+        return Ok(());
+    }
+    let history: Vec<Execution> = {
+        let _guard = store::lock(HISTORY_FILE).await;
+        store::load(app, HISTORY_FILE)?
+    };
+    let ran = |due: &Due, id: &PeripheralId| {
+        history.iter().any(|execution| {
+            execution.rule == due.rule
+                && execution.scheduled == due.scheduled
+                && execution.id == *id
+                && execution.error.is_none()
+        })
+    };
+
+    let mut executions = vec![];
+    let mut done = vec![];
+    for due in &due {
+        // lamps that already ran this occurrence on an earlier tick are not written again
+        let devices = due
+            .devices
+            .iter()
+            .filter(|id| !ran(due, id))
+            .collect::<Vec<_>>();
+        let results = join_all(devices.iter().map(|id| execute(app, id, &due.action))).await;
+        let mut failed = false;
+        for (id, result) in devices.into_iter().zip(results) {
+            let error = result.err();
+            if let Some(e) = &error {
+                warn!("schedule {} failed on {id}: {e}", due.rule);
+                failed = true;
+            }
+            executions.push(Execution {
+                rule: due.rule.clone(),
+                id: id.clone(),
+                scheduled: due.scheduled,
+                executed: Utc::now(),
+                catch_up: due.catch_up,
+                error: error.map(|e| e.to_string()),
+            });
+        }
+        if !failed {
+            done.push(due.rule.clone());
+        }
+    }
+
+    if !executions.is_empty() {
+        store::update(app, HISTORY_FILE, |history: &mut Vec<Execution>| {
+            for execution in executions {
+                // a lamp retried every tick while away is only recorded failing once
+                let repeated = execution.error.is_some()
+                    && history.iter().any(|item| {
+                        item.rule == execution.rule
+                            && item.scheduled == execution.scheduled
+                            && item.id == execution.id
+                            && item.error.is_some()
+                    });
+                if !repeated {
+                    history.push(execution);
+                }
+            }
+            let overflow = history.len().saturating_sub(HISTORY_LIMIT);
+            history.drain(..overflow);
+            Ok(())
+        })
+        .await?;
+    }
+    if !done.is_empty() {
+        store::update(app, SCHEDULE_FILE, |schedule: &mut Schedule| {
+            for rule in schedule.rules.iter_mut() {
+                // a rule edited meanwhile already starts after its edit
+                if done.contains(&rule.name) && rule.last_run.is_none_or(|last| last < now) {
+                    rule.last_run = Some(now);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Drives the host side schedule for as long as the app runs.
+pub fn watch(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = tick(&app).await {
+                warn!("schedule tick error: {e}");
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_schedule(app: AppHandle) -> Result<Schedule> {
+    let _guard = store::lock(SCHEDULE_FILE).await;
+    store::load(&app, SCHEDULE_FILE)
+}
+
+#[tauri::command]
+pub async fn add_schedule_rule(app: AppHandle, rule: ScheduleRule) -> Result<()> {
+    rule.recurrence.validate()?;
+    store::update(&app, SCHEDULE_FILE, |schedule: &mut Schedule| {
+        if schedule.rules.iter().any(|item| item.name == rule.name) {
+            return Err(anyhow!("schedule rule {} already exists", rule.name).into());
+        }
+        schedule.rules.push(ScheduleRule {
+            last_run: Some(Utc::now()),
+            ..rule
+        });
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn update_schedule_rule(app: AppHandle, name: String, rule: ScheduleRule) -> Result<()> {
+    rule.recurrence.validate()?;
+    store::update(&app, SCHEDULE_FILE, |schedule: &mut Schedule| {
+        if rule.name != name && schedule.rules.iter().any(|item| item.name == rule.name) {
+            return Err(anyhow!("schedule rule {} already exists", rule.name).into());
+        }
+        let item = schedule
+            .rules
+            .iter_mut()
+            .find(|item| item.name == name)
+            .ok_or(anyhow!("schedule rule {name} not found"))?;
+        *item = ScheduleRule {
+            last_run: Some(Utc::now()),
+            ..rule
+        };
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn remove_schedule_rule(app: AppHandle, name: String) -> Result<()> {
+    store::update(&app, SCHEDULE_FILE, |schedule: &mut Schedule| {
+        schedule.rules.retain(|item| item.name != name);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_holidays(app: AppHandle, holidays: Vec<NaiveDate>) -> Result<()> {
+    store::update(&app, SCHEDULE_FILE, |schedule: &mut Schedule| {
+        schedule.holidays = holidays;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_schedule_history(app: AppHandle) -> Result<Vec<Execution>> {
+    let _guard = store::lock(HISTORY_FILE).await;
+    store::load(&app, HISTORY_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, LocalResult, NaiveDateTime};
+
+    use super::*;
+
+    /// Central European time with the 2024 clock changes, at 01:00 UTC on March 31 and October 27.
+    #[derive(Debug, Clone, Copy)]
+    struct Cet;
+
+    impl Cet {
+        fn is_summer(utc: &NaiveDateTime) -> bool {
+            let change = |month, day| {
+                NaiveDate::from_ymd_opt(2024, month, day)
+                    .unwrap()
+                    .and_hms_opt(1, 0, 0)
+                    .unwrap()
+            };
+            *utc >= change(3, 31) && *utc < change(10, 27)
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let offsets = [3600, 7200]
+                .map(|seconds| FixedOffset::east_opt(seconds).unwrap())
+                .into_iter()
+                .filter(|offset| self.offset_from_utc_datetime(&(*local - *offset)) == *offset)
+                .collect::<Vec<_>>();
+            match offsets[..] {
+                [offset] => LocalResult::Single(offset),
+                [first, second] => LocalResult::Ambiguous(second, first),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            FixedOffset::east_opt(if Cet::is_summer(utc) { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    /// A UTC instant.
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        date(month, day).and_time(time(hour, minute)).and_utc()
+    }
+
+    fn weekdays(days: &[u32], hour: u32, minute: u32) -> Recurrence {
+        Recurrence::Weekdays {
+            days: days.to_vec(),
+            time: time(hour, minute),
+            skip_holidays: true,
+        }
+    }
+
+    #[test]
+    fn weekdays_and_holidays() {
+        // 2024-01-01 is a Monday
+        let rule = weekdays(&[1, 3], 7, 0);
+        assert_eq!(rule.occurs_on(date(1, 1), &[]), Some(time(7, 0)));
+        assert_eq!(rule.occurs_on(date(1, 2), &[]), None);
+        assert_eq!(rule.occurs_on(date(1, 3), &[]), Some(time(7, 0)));
+        assert_eq!(rule.occurs_on(date(1, 1), &[date(1, 1)]), None);
+    }
+
+    #[test]
+    fn intervals_count_from_the_start() {
+        let rule = Recurrence::Interval {
+            start: date(1, 10),
+            interval: 3,
+            time: time(20, 0),
+        };
+        assert_eq!(rule.occurs_on(date(1, 9), &[]), None);
+        assert_eq!(rule.occurs_on(date(1, 10), &[]), Some(time(20, 0)));
+        assert_eq!(rule.occurs_on(date(1, 12), &[]), None);
+        assert_eq!(rule.occurs_on(date(1, 13), &[]), Some(time(20, 0)));
+    }
+
+    #[test]
+    fn window_excludes_since_and_includes_now() {
+        let rule = weekdays(&[1, 2, 3, 4, 5, 6, 7], 7, 0);
+        // 07:00 CET is 06:00 UTC
+        let occurrence = at(1, 2, 6, 0);
+        assert_eq!(
+            rule.latest_between(at(1, 2, 5, 59), occurrence, &[], &Cet),
+            Some(occurrence)
+        );
+        assert_eq!(
+            rule.latest_between(occurrence, at(1, 2, 6, 30), &[], &Cet),
+            None
+        );
+        let once = Recurrence::Once { at: occurrence };
+        assert_eq!(
+            once.latest_between(at(1, 2, 5, 0), occurrence, &[], &Cet),
+            Some(occurrence)
+        );
+        assert_eq!(
+            once.latest_between(occurrence, at(1, 3, 0, 0), &[], &Cet),
+            None
+        );
+    }
+
+    #[test]
+    fn days_are_local() {
+        // 00:30 CET on Tuesday is still Monday in UTC
+        let rule = weekdays(&[2], 0, 30);
+        assert_eq!(
+            rule.latest_between(at(1, 1, 12, 0), at(1, 2, 12, 0), &[], &Cet),
+            Some(at(1, 1, 23, 30))
+        );
+        // 23:30 CET on Monday is 22:30 UTC, before the window
+        let rule = weekdays(&[1], 23, 30);
+        assert_eq!(
+            rule.latest_between(at(1, 1, 22, 31), at(1, 2, 12, 0), &[], &Cet),
+            None
+        );
+    }
+
+    #[test]
+    fn catch_up_takes_the_newest_missed_occurrence() {
+        let rule = weekdays(&[1, 2, 3, 4, 5, 6, 7], 7, 0);
+        assert_eq!(
+            rule.latest_between(at(1, 1, 12, 0), at(1, 5, 5, 0), &[], &Cet),
+            Some(at(1, 4, 6, 0))
+        );
+        assert_eq!(
+            rule.latest_between(at(1, 1, 12, 0), at(1, 5, 6, 0), &[], &Cet),
+            Some(at(1, 5, 6, 0))
+        );
+    }
+
+    #[test]
+    fn clock_changes() {
+        let daily = |hour| weekdays(&[1, 2, 3, 4, 5, 6, 7], hour, 30);
+        // 02:30 does not exist on March 31, it runs at 03:30 CEST
+        assert_eq!(
+            daily(2).latest_between(at(3, 30, 12, 0), at(3, 31, 12, 0), &[], &Cet),
+            Some(at(3, 31, 1, 30))
+        );
+        // 02:30 happens twice on October 27, the first one counts
+        assert_eq!(
+            daily(2).latest_between(at(10, 26, 12, 0), at(10, 27, 12, 0), &[], &Cet),
+            Some(at(10, 27, 0, 30))
+        );
+        assert_eq!(
+            daily(2).latest_between(at(10, 27, 0, 30), at(10, 27, 12, 0), &[], &Cet),
+            None
+        );
+        // other times keep their local time across the change
+        assert_eq!(
+            daily(7).latest_between(at(3, 31, 0, 0), at(3, 31, 12, 0), &[], &Cet),
+            Some(at(3, 31, 5, 30))
+        );
+    }
+
+    #[test]
+    fn invalid_recurrences() {
+        assert!(weekdays(&[1, 7], 7, 0).validate().is_ok());
+        assert!(weekdays(&[], 7, 0).validate().is_err());
+        assert!(weekdays(&[0], 7, 0).validate().is_err());
+        assert!(weekdays(&[8], 7, 0).validate().is_err());
+        let interval = |interval| Recurrence::Interval {
+            start: date(1, 1),
+            interval,
+            time: time(7, 0),
+        };
+        assert!(interval(1).validate().is_ok());
+        assert!(interval(0).validate().is_err());
+    }
+}
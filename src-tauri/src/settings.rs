@@ -0,0 +1,157 @@
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+use crate::error::Result;
+use crate::{solar, store};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// The settings last read or written, they are needed on every color sent to a lamp.
+static CACHE: Mutex<Option<Settings>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    /// East positive.
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Used to compute sunrise and sunset locally.
+    pub location: Option<Location>,
+    /// Gamma applied to colors sent to the lamps, 1.0 disables the correction
+    /// and around 2.2 makes brightness steps look even on most LEDs.
+    pub gamma: f64,
+    /// The Bluetooth adapter picked with `select_adapter`,
+    /// the first one is used when unset or missing.
+    pub adapter: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            location: None,
+            gamma: 1.0,
+            adapter: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Rejects values that would break color correction or the sun times.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.gamma.is_finite() || self.gamma <= 0.0 {
+            bail!("gamma must be a positive number");
+        }
+        if let Some(Location {
+            latitude,
+            longitude,
+        }) = self.location
+        {
+            if !(-90.0..=90.0).contains(&latitude) {
+                bail!("latitude must be between -90 and 90");
+            }
+            if !(-180.0..=180.0).contains(&longitude) {
+                bail!("longitude must be between -180 and 180");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn cache() -> MutexGuard<'static, Option<Settings>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Settings {
+    /// Reads the settings file once, later calls get the cached copy.
+    pub fn load(app: &AppHandle) -> Result<Self> {
+        if let Some(settings) = cache().clone() {
+            return Ok(settings);
+        }
+        let settings: Self = store::load(app, SETTINGS_FILE)?;
+        *cache() = Some(settings.clone());
+        Ok(settings)
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<()> {
+        store::save(app, SETTINGS_FILE, self)?;
+        *cache() = Some(self.clone());
+        Ok(())
+    }
+
+    /// Loads the settings, lets `f` change them and saves the result.
+    pub async fn update(app: &AppHandle, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        let _guard = store::lock(SETTINGS_FILE).await;
+        let mut settings = Self::load(app)?;
+        f(&mut settings)?;
+        settings.save(app)
+    }
+}
+
+#[tauri::command]
+pub async fn get_settings(app: AppHandle) -> Result<Settings> {
+    Settings::load(&app)
+}
+
+/// Changes the fields present in `settings`, the others keep their value.
+#[tauri::command]
+pub async fn set_settings(app: AppHandle, settings: Value) -> Result<()> {
+    let Value::Object(fields) = settings else {
+        return Err(anyhow!("settings must be an object").into());
+    };
+    Settings::update(&app, |settings| {
+        let mut value = serde_json::to_value(&*settings)?;
+        if let Value::Object(current) = &mut value {
+            current.extend(fields);
+        }
+        let merged: Settings = serde_json::from_value(value)?;
+        merged.validate()?;
+        *settings = merged;
+        Ok(())
+    })
+    .await?;
+    solar::sync(&app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(latitude: f64, longitude: f64) -> Settings {
+        Settings {
+            location: Some(Location {
+                latitude,
+                longitude,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gamma_must_be_positive() {
+        assert!(Settings::default().validate().is_ok());
+        for gamma in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let settings = Settings {
+                gamma,
+                ..Default::default()
+            };
+            assert!(settings.validate().is_err(), "{gamma}");
+        }
+    }
+
+    #[test]
+    fn location_must_be_on_earth() {
+        assert!(at(90.0, -180.0).validate().is_ok());
+        assert!(at(-33.9, 151.2).validate().is_ok());
+        assert!(at(90.1, 0.0).validate().is_err());
+        assert!(at(0.0, 180.5).validate().is_err());
+        assert!(at(f64::NAN, 0.0).validate().is_err());
+    }
+}
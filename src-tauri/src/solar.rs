@@ -0,0 +1,320 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use btleplug::platform::PeripheralId;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::warn;
+
+use crate::ble::write_timer_event;
+use crate::error::Result;
+use crate::led::LedCommand;
+use crate::settings::{Location, Settings};
+use crate::state::connected_led;
+use crate::store;
+use crate::timer::{TimeTask, TimeTaskKind, TimerEvent};
+
+const SOLAR_TASKS_FILE: &str = "solar_tasks.json";
+const SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Julian date of the unix epoch.
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Julian date of 2000-01-01 12:00.
+const J2000: f64 = 2451545.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SolarEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// A timer task relative to sunrise or sunset, kept on the device as a `day` task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolarTask {
+    pub id: PeripheralId,
+    pub name: String,
+    pub operation: LedCommand,
+    pub event: SolarEvent,
+    /// Minutes after the event, negative values trigger before it.
+    pub offset: i64,
+    /// The trigger time last written to the device.
+    #[serde(default)]
+    pub synced: Option<DateTime<Utc>>,
+    /// Why the last attempt to write the task failed.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Removed while the lamp was away, the device task is removed on the next sync.
+    #[serde(default)]
+    pub removed: bool,
+}
+
+/// Computes sunrise and sunset of a day with the sunrise equation,
+/// returns `None` during polar day or night.
+pub fn sun_times(date: NaiveDate, location: Location) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let to_rad = PI / 180.0;
+    let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64;
+
+    let mean_solar_noon = days - location.longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.0) * to_rad;
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude =
+        (anomaly / to_rad + center + 180.0 + 102.9372).rem_euclid(360.0) * to_rad;
+    let transit = J2000 + mean_solar_noon + 0.0053 * anomaly.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * (23.4397 * to_rad).sin()).asin();
+    let latitude = location.latitude * to_rad;
+    let cos_hour_angle = ((-0.833 * to_rad).sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos() / to_rad / 360.0;
+
+    let to_time = |jd: f64| DateTime::from_timestamp(((jd - UNIX_EPOCH_JD) * 86400.0) as i64, 0);
+    Some((
+        to_time(transit - hour_angle)?,
+        to_time(transit + hour_angle)?,
+    ))
+}
+
+impl SolarTask {
+    /// The next time this task should fire, looking at today and tomorrow.
+    pub fn next_trigger(&self, location: Location, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&Local).date_naive();
+        [today, today.succ_opt()?]
+            .into_iter()
+            .filter_map(|date| {
+                let (sunrise, sunset) = sun_times(date, location)?;
+                let time = match self.event {
+                    SolarEvent::Sunrise => sunrise,
+                    SolarEvent::Sunset => sunset,
+                };
+                Some(time + TimeDelta::minutes(self.offset))
+            })
+            .find(|time| *time > now)
+    }
+
+    fn time_task(&self, delay: DateTime<Utc>) -> TimeTask {
+        TimeTask {
+            name: self.name.clone(),
+            operation: self.operation.clone(),
+            kind: TimeTaskKind::Day { delay },
+        }
+    }
+}
+
+fn same_minute(a: DateTime<Utc>, b: DateTime<Utc>) -> bool {
+    let (a, b) = (a.with_timezone(&Local), b.with_timezone(&Local));
+    a.hour() == b.hour() && a.minute() == b.minute()
+}
+
+/// Rewrites the `day` tasks of connected devices whose solar time has shifted,
+/// and removes the device tasks of solar tasks removed while their lamp was away.
+///
+/// A lamp failing does not stop the others, its error is kept with the task.
+pub async fn sync(app: &AppHandle) -> Result<()> {
+    // held while writing to the lamps, so edits cannot slip in before the file is saved
+    let _guard = store::lock(SOLAR_TASKS_FILE).await;
+    let location = Settings::load(app)?.location;
+    let mut tasks: Vec<SolarTask> = store::load(app, SOLAR_TASKS_FILE)?;
+    let now = Utc::now();
+    let mut changed = false;
+
+    for task in tasks.iter_mut() {
+        // removed tasks only have their device task taken off
+        let trigger = if task.removed {
+            None
+        } else {
+            let Some(trigger) = location.and_then(|location| task.next_trigger(location, now))
+            else {
+                continue;
+            };
+            if task
+                .synced
+                .is_some_and(|synced| same_minute(synced, trigger))
+            {
+                continue;
+            }
+            Some(trigger)
+        };
+        let Some(led) = connected_led(app, &task.id).await else {
+            continue;
+        };
+        changed = true;
+
+        // each step is recorded on its own, a failed add leaves the task unsynced
+        // instead of pointing at a device task that was already removed
+        if task.synced.is_some() {
+            let remove = TimerEvent::RemoveTask(task.name.clone());
+            if let Err(e) = led.run(|led| write_timer_event(led, remove)).await {
+                warn!("remove solar task {} of {} failed: {e}", task.name, task.id);
+                task.error = Some(e.to_string());
+                continue;
+            }
+            task.synced = None;
+        }
+        task.error = None;
+        if let Some(trigger) = trigger {
+            let add = TimerEvent::AddTask(task.time_task(trigger));
+            match led.run(|led| write_timer_event(led, add)).await {
+                Ok(()) => task.synced = Some(trigger),
+                Err(e) => {
+                    warn!("add solar task {} of {} failed: {e}", task.name, task.id);
+                    task.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    if changed {
+        tasks.retain(|task| !(task.removed && task.synced.is_none()));
+        store::save(app, SOLAR_TASKS_FILE, &tasks)?;
+    }
+    Ok(())
+}
+
+/// Keeps solar tasks in sync as the sun times drift through the year.
+pub fn watch(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sync(&app).await {
+                warn!("solar sync error: {e}");
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_solar_tasks(app: AppHandle) -> Result<Vec<SolarTask>> {
+    let tasks: Vec<SolarTask> = store::load(&app, SOLAR_TASKS_FILE)?;
+    Ok(tasks.into_iter().filter(|task| !task.removed).collect())
+}
+
+#[tauri::command]
+pub async fn add_solar_task(app: AppHandle, task: SolarTask) -> Result<()> {
+    store::update(&app, SOLAR_TASKS_FILE, |tasks: &mut Vec<SolarTask>| {
+        let existing = tasks
+            .iter()
+            .position(|item| item.id == task.id && item.name == task.name);
+        match existing {
+            Some(index) if !tasks[index].removed => {
+                return Err(anyhow!("solar task {} already exists", task.name).into());
+            }
+            // takes over the device task still waiting to be removed, the next sync replaces it
+            Some(index) => {
+                let synced = tasks[index].synced;
+                tasks[index] = SolarTask {
+                    synced,
+                    error: None,
+                    removed: false,
+                    ..task
+                };
+            }
+            None => tasks.push(SolarTask {
+                synced: None,
+                error: None,
+                removed: false,
+                ..task
+            }),
+        }
+        Ok(())
+    })
+    .await?;
+    sync(&app).await
+}
+
+/// Removes the task from the lamp, or marks it so the next sync removes it
+/// once the lamp is connected again.
+#[tauri::command]
+pub async fn remove_solar_task(app: AppHandle, id: PeripheralId, name: String) -> Result<()> {
+    let _guard = store::lock(SOLAR_TASKS_FILE).await;
+    let mut tasks: Vec<SolarTask> = store::load(&app, SOLAR_TASKS_FILE)?;
+    let Some(index) = tasks
+        .iter()
+        .position(|item| item.id == id && item.name == name && !item.removed)
+    else {
+        return Err(anyhow!("solar task {name} not found").into());
+    };
+    let removed = match (tasks[index].synced, connected_led(&app, &id).await) {
+        (None, _) => true,
+        (Some(_), Some(led)) => {
+            let remove = TimerEvent::RemoveTask(name);
+            led.run(|led| write_timer_event(led, remove))
+                .await
+                .inspect_err(|e| warn!("remove solar task of {id} failed: {e}"))
+                .is_ok()
+        }
+        (Some(_), None) => false,
+    };
+    if removed {
+        tasks.remove(index);
+    } else {
+        tasks[index].removed = true;
+    }
+    store::save(&app, SOLAR_TASKS_FILE, &tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn assert_near(time: DateTime<Utc>, expected: DateTime<Utc>) {
+        let error = (time - expected).num_seconds().abs();
+        assert!(error <= 120, "{time} is not near {expected}");
+    }
+
+    #[test]
+    fn london_midsummer() {
+        let (sunrise, sunset) = sun_times(date(2024, 6, 21), LONDON).unwrap();
+        assert_near(
+            sunrise,
+            Utc.with_ymd_and_hms(2024, 6, 21, 3, 43, 0).unwrap(),
+        );
+        assert_near(
+            sunset,
+            Utc.with_ymd_and_hms(2024, 6, 21, 20, 21, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn london_midwinter() {
+        let (sunrise, sunset) = sun_times(date(2024, 12, 21), LONDON).unwrap();
+        assert_near(
+            sunrise,
+            Utc.with_ymd_and_hms(2024, 12, 21, 8, 4, 0).unwrap(),
+        );
+        assert_near(
+            sunset,
+            Utc.with_ymd_and_hms(2024, 12, 21, 15, 53, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        assert!(sun_times(date(2024, 6, 21), TROMSO).is_none());
+        assert!(sun_times(date(2024, 12, 21), TROMSO).is_none());
+        assert!(sun_times(date(2024, 3, 20), TROMSO).is_some());
+    }
+}
@@ -1,35 +1,232 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use btleplug::{
-    api::Manager as _,
+    api::{Central as _, Manager as _, Peripheral as _},
     platform::{Adapter, Manager, PeripheralId},
 };
 use tauri::async_runtime::Mutex;
+use tauri::{AppHandle, Manager as _};
+use tokio::sync::{MappedMutexGuard, MutexGuard};
 
-use crate::led::Led;
+use crate::error::{Error, Result};
+use crate::{
+    adapter::RadioState,
+    health::Health,
+    led::Led,
+    playlist::PlaylistPlayer,
+    preview::Preview,
+    scan::{PausedScan, ScanSession},
+};
 
 #[derive(Debug)]
 pub struct BleState {
+    pub manager: Manager,
     pub adapter: Adapter,
+    /// The `adapter_info` of `adapter`, `None` once it has been unplugged.
+    pub adapter_name: Option<String>,
+    pub radio: RadioState,
     pub leds: HashMap<PeripheralId, Led>,
+    pub previews: HashMap<PeripheralId, Preview>,
+    pub playlists: HashMap<PeripheralId, PlaylistPlayer>,
+    pub scan: Option<ScanSession>,
+    /// The scan waiting for the radio to come back on.
+    pub paused_scan: Option<PausedScan>,
+    /// Lamps being connected for the first time.
+    pub connecting: HashSet<PeripheralId>,
+    /// Lamps with a reconnect in progress.
+    pub reconnecting: HashSet<PeripheralId>,
+    pub health: HashMap<PeripheralId, Health>,
 }
 
 impl BleState {
-    pub async fn new() -> anyhow::Result<Self> {
+    /// Uses the `preferred` adapter when it is plugged in, the first one otherwise.
+    pub async fn new(preferred: Option<&str>) -> anyhow::Result<Self> {
         let manager = Manager::new().await?;
-        let adapter = manager
-            .adapters()
-            .await?
-            .into_iter()
-            .next()
-            .ok_or(anyhow!("No adapters found"))?;
+        let mut adapters = adapters(&manager).await?;
+        if adapters.is_empty() {
+            bail!("No adapters found");
+        }
+        let index = adapters
+            .iter()
+            .position(|(name, _)| Some(name.as_str()) == preferred)
+            .unwrap_or(0);
+        let (name, adapter) = adapters.swap_remove(index);
+        let radio = adapter
+            .adapter_state()
+            .await
+            .map_or(RadioState::Unknown, RadioState::from);
 
         Ok(Self {
+            manager,
             adapter,
+            adapter_name: Some(name),
+            radio,
             leds: HashMap::new(),
+            previews: HashMap::new(),
+            playlists: HashMap::new(),
+            scan: None,
+            paused_scan: None,
+            connecting: HashSet::new(),
+            reconnecting: HashSet::new(),
+            health: HashMap::new(),
+        })
+    }
+}
+
+/// The scan work left by a radio change.
+#[derive(Debug)]
+pub enum RadioWork {
+    Idle,
+    /// The radio went off, the scan is paused.
+    Pause(ScanSession),
+    /// The radio is back on, the paused scan is resumed.
+    Resume {
+        paused: PausedScan,
+        adapter: Adapter,
+        /// Put back when the scan cannot be resumed, so the change is tried again.
+        previous: RadioState,
+    },
+}
+
+/// What runs on the lamps of a state being torn down, taken out of it so it can be
+/// stopped after the state lock is released.
+#[derive(Debug, Default)]
+pub struct Detached {
+    scan: Option<ScanSession>,
+    paused_scan: Option<PausedScan>,
+    previews: Vec<Preview>,
+    playlists: Vec<PlaylistPlayer>,
+    leds: Vec<Led>,
+}
+
+impl Detached {
+    /// Stops everything running on the lamps and disconnects them.
+    pub async fn stop(self) {
+        if let Some(scan) = self.scan {
+            scan.stop().await;
+        }
+        if let Some(scan) = self.paused_scan {
+            scan.cancel();
+        }
+        drop(self.previews);
+        for player in self.playlists {
+            player.stop().await.ok();
+        }
+        for led in self.leds {
+            led.peripheral.disconnect().await.ok();
+        }
+    }
+}
+
+impl BleState {
+    /// Takes out everything running on the lamps, to be stopped with `Detached::stop`.
+    fn detach(&mut self) -> Detached {
+        self.connecting.clear();
+        self.reconnecting.clear();
+        self.health.clear();
+        Detached {
+            scan: self.scan.take(),
+            paused_scan: self.paused_scan.take(),
+            previews: self.previews.drain().map(|(_, preview)| preview).collect(),
+            playlists: self.playlists.drain().map(|(_, player)| player).collect(),
+            leds: self.leds.drain().map(|(_, led)| led).collect(),
+        }
+    }
+
+    /// Tears the state down for good, e.g. before `init` builds a new one.
+    pub async fn shut_down(mut self) {
+        self.detach().stop().await;
+    }
+
+    /// Moves to another adapter, lamps connected through the old one are dropped.
+    ///
+    /// Returns what ran on them, stop it once the state lock is released.
+    #[must_use]
+    pub fn switch_adapter(&mut self, name: Option<String>, adapter: Option<Adapter>) -> Detached {
+        let detached = self.detach();
+        if let Some(adapter) = adapter {
+            self.adapter = adapter;
+        }
+        self.adapter_name = name;
+        // polled again by the adapter watch
+        self.radio = RadioState::Unknown;
+        detached
+    }
+
+    /// Records the radio state, returns `None` when it did not change.
+    ///
+    /// A running scan is taken out to be paused when the radio goes off, a paused one
+    /// to be resumed once it is back, both after the state lock is released.
+    pub fn set_radio(&mut self, radio: RadioState) -> Option<RadioWork> {
+        if self.radio == radio {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.radio, radio);
+        Some(if radio == RadioState::Off {
+            self.scan.take().map_or(RadioWork::Idle, RadioWork::Pause)
+        } else {
+            self.paused_scan
+                .take()
+                .map_or(RadioWork::Idle, |paused| RadioWork::Resume {
+                    paused,
+                    adapter: self.adapter.clone(),
+                    previous,
+                })
         })
     }
+
+    /// Off only when the adapter says so, platforms that cannot tell are taken to be on.
+    pub fn radio_on(&self) -> bool {
+        self.radio != RadioState::Off
+    }
+}
+
+/// All adapters with their `adapter_info`, which is used to tell them apart.
+/// Adapters going away while being listed are left out.
+pub async fn adapters(manager: &Manager) -> anyhow::Result<Vec<(String, Adapter)>> {
+    let mut adapters = vec![];
+    for adapter in manager.adapters().await? {
+        if let Ok(name) = adapter.adapter_info().await {
+            adapters.push((name, adapter));
+        }
+    }
+    Ok(adapters)
+}
+
+/// The BLE state, `None` until `init` has run.
+#[derive(Debug, Default)]
+pub struct AppState(Mutex<Option<BleState>>);
+
+impl AppState {
+    /// Locks the BLE state, failing with `NotInitialized` before `init`.
+    pub async fn lock(&self) -> Result<MappedMutexGuard<'_, BleState>> {
+        MutexGuard::try_map(self.0.lock().await, Option::as_mut).map_err(|_| Error::NotInitialized)
+    }
+
+    /// Takes the BLE state out, commands fail with `NotInitialized` until it is put back.
+    pub async fn take(&self) -> Option<BleState> {
+        self.0.lock().await.take()
+    }
+
+    pub async fn set(&self, ble_state: BleState) {
+        *self.0.lock().await = Some(ble_state);
+    }
+}
+
+/// Looks up a connected led outside of a command, `None` before `init` or when not connected.
+pub async fn connected_led(app: &AppHandle, id: &PeripheralId) -> Option<Led> {
+    let state = app.try_state::<AppState>()?;
+    let ble_state = state.lock().await.ok()?;
+    ble_state.leds.get(id).cloned()
 }
 
-pub type AppState = Mutex<BleState>;
+/// Looks up a connected led, the lock is released before the led is used.
+pub async fn get_led(state: &AppState, id: &PeripheralId) -> Result<Led> {
+    let ble_state = state.lock().await?;
+    Ok(ble_state
+        .leds
+        .get(id)
+        .cloned()
+        .ok_or(anyhow!("Led not found"))?)
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::error::Result;
+
+/// One lock per file, so read-modify-write cycles of a file never interleave.
+static LOCKS: std::sync::Mutex<BTreeMap<&'static str, Arc<Mutex<()>>>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+fn path(app: &AppHandle, file: &str) -> Result<PathBuf> {
+    Ok(app.path().app_data_dir()?.join(file))
+}
+
+/// Reads a JSON file from the app data directory, missing files give the default value.
+pub fn load<T>(app: &AppHandle, file: &str) -> Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    let path = path(app, file)?;
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let data = fs::read(path).map_err(anyhow::Error::from)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Writes a JSON file into the app data directory, creating the directory when needed.
+pub fn save<T>(app: &AppHandle, file: &str, value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let data = serde_json::to_vec_pretty(value)?;
+    write(&path(app, file)?, &data).map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+/// Writes a temporary file next to `path` and renames it over `path`,
+/// so a crash while writing leaves the previous content instead of a truncated file.
+fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+/// Locks `file` for a read-modify-write cycle `update` cannot express,
+/// e.g. one writing to the lamps before saving.
+pub async fn lock(file: &'static str) -> OwnedMutexGuard<()> {
+    let lock = LOCKS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(file)
+        .or_default()
+        .clone();
+    lock.lock_owned().await
+}
+
+/// Loads a file, lets `f` change the value and saves it, with the file locked throughout.
+///
+/// Nothing is saved when `f` fails.
+pub async fn update<T, R>(
+    app: &AppHandle,
+    file: &'static str,
+    f: impl FnOnce(&mut T) -> Result<R>,
+) -> Result<R>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    let _guard = lock(file).await;
+    let mut value = load(app, file)?;
+    let output = f(&mut value)?;
+    save(app, file, &value)?;
+    Ok(output)
+}
+
+/// Serde default of flags that are on unless turned off.
+pub fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_replace_the_file() {
+        let dir = std::env::temp_dir().join(format!("store-test-{}", std::process::id()));
+        let path = dir.join("nested").join("data.json");
+        write(&path, b"[1]").unwrap();
+        write(&path, b"[1, 2]").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"[1, 2]");
+        let files = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,503 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::led::LedCommand;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TimeTaskKind {
+    Once {
+        #[serde(rename = "endTime")]
+        end_time: DateTime<Utc>,
+    },
+    Day {
+        delay: DateTime<Utc>,
+    },
+    Week {
+        /// 1 is Monday, 7 is Sunday.
+        #[serde(rename = "dayOfWeek")]
+        day_of_week: u8,
+        delay: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeTask {
+    pub name: String,
+    pub operation: LedCommand,
+    #[serde(flatten)]
+    pub kind: TimeTaskKind,
+}
+
+/// The events written to the time task characteristic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum TimerEvent {
+    AddTask(TimeTask),
+    RemoveTask(String),
+}
+
+/// A problem found in the task set of a device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TimeTaskIssue {
+    DuplicateName {
+        name: String,
+    },
+    /// Two tasks with different operations fire in the same minute.
+    Conflict {
+        first: String,
+        second: String,
+    },
+    PastOnce {
+        name: String,
+    },
+}
+
+impl Display for TimeTaskIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeTaskIssue::DuplicateName { name } => write!(f, "duplicate task name {name}"),
+            TimeTaskIssue::Conflict { first, second } => {
+                write!(f, "task {first} conflicts with task {second}")
+            }
+            TimeTaskIssue::PastOnce { name } => write!(f, "task {name} is in the past"),
+        }
+    }
+}
+
+/// The local minute a task fires at, with the date or weekday it is bound to.
+struct Trigger {
+    date: Option<NaiveDate>,
+    weekday: Option<u32>,
+    minute: (u32, u32),
+}
+
+impl TimeTask {
+    fn trigger(&self) -> Trigger {
+        let (time, date, weekday) = match &self.kind {
+            TimeTaskKind::Once { end_time } => {
+                let time = end_time.with_timezone(&Local);
+                let date = time.date_naive();
+                (time, Some(date), Some(date.weekday().number_from_monday()))
+            }
+            TimeTaskKind::Day { delay } => (delay.with_timezone(&Local), None, None),
+            TimeTaskKind::Week { day_of_week, delay } => {
+                (delay.with_timezone(&Local), None, Some(*day_of_week as u32))
+            }
+        };
+        Trigger {
+            date,
+            weekday,
+            minute: (time.hour(), time.minute()),
+        }
+    }
+
+    /// Whether both tasks can fire in the same minute.
+    pub fn collides(&self, other: &TimeTask) -> bool {
+        let (a, b) = (self.trigger(), other.trigger());
+        a.minute == b.minute && same(a.date, b.date) && same(a.weekday, b.weekday)
+    }
+}
+
+/// Unbound sides match anything.
+fn same<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+/// Checks a whole task set for duplicate names, conflicting operations and expired `once` tasks.
+pub fn validate(tasks: &[TimeTask], now: DateTime<Utc>) -> Vec<TimeTaskIssue> {
+    let mut issues = vec![];
+    for (index, task) in tasks.iter().enumerate() {
+        if let TimeTaskKind::Once { end_time } = task.kind {
+            if end_time <= now {
+                issues.push(TimeTaskIssue::PastOnce {
+                    name: task.name.clone(),
+                });
+            }
+        }
+        for other in &tasks[index + 1..] {
+            if other.name == task.name {
+                issues.push(TimeTaskIssue::DuplicateName {
+                    name: task.name.clone(),
+                });
+            } else if other.operation != task.operation && task.collides(other) {
+                issues.push(TimeTaskIssue::Conflict {
+                    first: task.name.clone(),
+                    second: other.name.clone(),
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Validates the task set that results from applying `event`,
+/// only reporting the issues the event introduces.
+pub fn validate_event(
+    tasks: &[TimeTask],
+    event: &TimerEvent,
+    now: DateTime<Utc>,
+) -> Vec<TimeTaskIssue> {
+    let TimerEvent::AddTask(task) = event else {
+        return vec![];
+    };
+    let before = validate(tasks, now);
+    let mut after = tasks.to_vec();
+    after.push(task.clone());
+    validate(&after, now)
+        .into_iter()
+        .filter(|issue| !before.contains(issue))
+        .collect()
+}
+
+#[tauri::command]
+pub async fn validate_time_tasks(tasks: Vec<TimeTask>) -> Vec<TimeTaskIssue> {
+    validate(&tasks, Utc::now())
+}
+
+/// The minimal set of events turning the device task list into the desired one.
+#[derive(Debug, Clone, Default)]
+pub struct TimeTaskDiff {
+    /// Tasks to remove, including changed tasks which are added again.
+    pub remove: Vec<TimeTask>,
+    pub add: Vec<TimeTask>,
+    pub unchanged: Vec<String>,
+}
+
+impl TimeTaskDiff {
+    pub fn new(current: &[TimeTask], desired: &[TimeTask]) -> Self {
+        let mut diff = Self::default();
+        for task in current {
+            if desired.contains(task) {
+                diff.unchanged.push(task.name.clone());
+            } else {
+                diff.remove.push(task.clone());
+            }
+        }
+        diff.add = desired
+            .iter()
+            .filter(|task| !current.contains(task))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// Events in the order they are applied, removals first so changed tasks keep their name.
+    pub fn events(&self) -> Vec<TimerEvent> {
+        self.remove
+            .iter()
+            .map(|task| TimerEvent::RemoveTask(task.name.clone()))
+            .chain(self.add.iter().cloned().map(TimerEvent::AddTask))
+            .collect()
+    }
+}
+
+/// What `sync_time_tasks` changed on the device.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimeTaskSync {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl From<&TimeTaskDiff> for TimeTaskSync {
+    fn from(diff: &TimeTaskDiff) -> Self {
+        let is_added = |name: &String| diff.add.iter().any(|task| &task.name == name);
+        let mut sync = Self {
+            unchanged: diff.unchanged.clone(),
+            ..Default::default()
+        };
+        for task in &diff.remove {
+            if is_added(&task.name) {
+                sync.updated.push(task.name.clone());
+            } else {
+                sync.removed.push(task.name.clone());
+            }
+        }
+        sync.added = diff
+            .add
+            .iter()
+            .map(|task| task.name.clone())
+            .filter(|name| !sync.updated.contains(name))
+            .collect();
+        sync
+    }
+}
+
+impl TimerEvent {
+    /// The event undoing this one, given the task list it was applied to.
+    pub fn inverse(&self, before: &[TimeTask]) -> Option<TimerEvent> {
+        match self {
+            TimerEvent::AddTask(task) => Some(TimerEvent::RemoveTask(task.name.clone())),
+            TimerEvent::RemoveTask(name) => before
+                .iter()
+                .find(|task| &task.name == name)
+                .cloned()
+                .map(TimerEvent::AddTask),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// A local time in the first week of 2030, the 7th is a Monday.
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2030, 1, day, hour, minute, 0)
+            .single()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn task(name: &str, operation: LedCommand, kind: TimeTaskKind) -> TimeTask {
+        TimeTask {
+            name: name.into(),
+            operation,
+            kind,
+        }
+    }
+
+    fn once(name: &str, operation: LedCommand, end_time: DateTime<Utc>) -> TimeTask {
+        task(name, operation, TimeTaskKind::Once { end_time })
+    }
+
+    fn day(name: &str, operation: LedCommand, delay: DateTime<Utc>) -> TimeTask {
+        task(name, operation, TimeTaskKind::Day { delay })
+    }
+
+    fn week(name: &str, operation: LedCommand, day_of_week: u8, delay: DateTime<Utc>) -> TimeTask {
+        task(name, operation, TimeTaskKind::Week { day_of_week, delay })
+    }
+
+    fn conflict(first: &str, second: &str) -> TimeTaskIssue {
+        TimeTaskIssue::Conflict {
+            first: first.into(),
+            second: second.into(),
+        }
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let tasks = [
+            day("wake", LedCommand::Open, at(1, 7, 0)),
+            day("wake", LedCommand::Open, at(1, 8, 0)),
+        ];
+        assert_eq!(
+            validate(&tasks, at(1, 0, 0)),
+            [TimeTaskIssue::DuplicateName {
+                name: "wake".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn same_minute_with_other_operation_conflicts() {
+        let now = at(1, 0, 0);
+        // a day task fires every day, so it meets any task of the same minute
+        let tasks = [
+            day("on", LedCommand::Open, at(2, 7, 30)),
+            once("off", LedCommand::Close, at(9, 7, 30)),
+            week("reset", LedCommand::Reset, 3, at(4, 7, 30)),
+        ];
+        assert_eq!(
+            validate(&tasks, now),
+            [
+                conflict("on", "off"),
+                conflict("on", "reset"),
+                conflict("off", "reset"),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_operation_or_minute_apart_is_fine() {
+        let tasks = [
+            day("on", LedCommand::Open, at(2, 7, 30)),
+            once("again", LedCommand::Open, at(9, 7, 30)),
+            day("off", LedCommand::Close, at(2, 7, 31)),
+        ];
+        assert!(validate(&tasks, at(1, 0, 0)).is_empty());
+    }
+
+    #[test]
+    fn bound_days_must_match() {
+        let now = at(1, 0, 0);
+        // the 9th is a Wednesday
+        let monday = week("monday", LedCommand::Open, 1, at(7, 22, 0));
+        let wednesday = once("wednesday", LedCommand::Close, at(9, 22, 0));
+        let other_date = once("other date", LedCommand::Open, at(10, 22, 0));
+        assert!(validate(&[monday, wednesday.clone()], now).is_empty());
+        assert!(validate(&[wednesday.clone(), other_date], now).is_empty());
+
+        let also_wednesday = week("also wednesday", LedCommand::Open, 3, at(7, 22, 0));
+        assert_eq!(
+            validate(&[wednesday, also_wednesday], now),
+            [conflict("wednesday", "also wednesday")]
+        );
+    }
+
+    #[test]
+    fn past_once() {
+        let tasks = [
+            once("gone", LedCommand::Open, at(1, 6, 0)),
+            once("due now", LedCommand::Open, at(1, 7, 0)),
+            once("later", LedCommand::Open, at(1, 8, 0)),
+        ];
+        assert_eq!(
+            validate(&tasks, at(1, 7, 0)),
+            [
+                TimeTaskIssue::PastOnce {
+                    name: "gone".into()
+                },
+                TimeTaskIssue::PastOnce {
+                    name: "due now".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn event_reports_only_new_issues() {
+        let now = at(1, 12, 0);
+        let tasks = [
+            once("gone", LedCommand::Open, at(1, 6, 0)),
+            day("on", LedCommand::Open, at(2, 7, 30)),
+            day("on", LedCommand::Open, at(2, 8, 30)),
+        ];
+        assert_eq!(validate(&tasks, now).len(), 2);
+
+        let add = TimerEvent::AddTask(day("off", LedCommand::Close, at(2, 7, 30)));
+        assert_eq!(validate_event(&tasks, &add, now), [conflict("on", "off")]);
+        let harmless = TimerEvent::AddTask(day("dim", LedCommand::Close, at(2, 9, 0)));
+        assert!(validate_event(&tasks, &harmless, now).is_empty());
+        let remove = TimerEvent::RemoveTask("on".into());
+        assert!(validate_event(&tasks, &remove, now).is_empty());
+    }
+
+    fn names(tasks: &[TimeTask]) -> Vec<&str> {
+        tasks.iter().map(|task| task.name.as_str()).collect()
+    }
+
+    /// Applies events the way the firmware does.
+    fn apply(tasks: &mut Vec<TimeTask>, event: &TimerEvent) {
+        match event {
+            TimerEvent::AddTask(task) => tasks.push(task.clone()),
+            TimerEvent::RemoveTask(name) => tasks.retain(|task| &task.name != name),
+        }
+    }
+
+    /// `keep` stays, `move` changes its time, `drop` goes away and `new` is added.
+    fn sync_case() -> (Vec<TimeTask>, Vec<TimeTask>) {
+        let current = vec![
+            day("keep", LedCommand::Open, at(2, 7, 0)),
+            day("move", LedCommand::Close, at(2, 22, 0)),
+            week("drop", LedCommand::Open, 6, at(2, 9, 0)),
+        ];
+        let desired = vec![
+            day("keep", LedCommand::Open, at(2, 7, 0)),
+            day("move", LedCommand::Close, at(2, 23, 0)),
+            once("new", LedCommand::Reset, at(9, 12, 0)),
+        ];
+        (current, desired)
+    }
+
+    #[test]
+    fn diff_classifies_tasks() {
+        let (current, desired) = sync_case();
+        let diff = TimeTaskDiff::new(&current, &desired);
+        assert_eq!(names(&diff.remove), ["move", "drop"]);
+        assert_eq!(names(&diff.add), ["move", "new"]);
+        assert_eq!(diff.unchanged, ["keep"]);
+
+        let sync = TimeTaskSync::from(&diff);
+        assert_eq!(sync.added, ["new"]);
+        assert_eq!(sync.removed, ["drop"]);
+        assert_eq!(sync.updated, ["move"]);
+        assert_eq!(sync.unchanged, ["keep"]);
+    }
+
+    #[test]
+    fn diff_of_equal_lists_is_empty() {
+        let (current, _) = sync_case();
+        let diff = TimeTaskDiff::new(&current, &current);
+        assert!(diff.events().is_empty());
+        assert_eq!(diff.unchanged, ["keep", "move", "drop"]);
+    }
+
+    #[test]
+    fn removals_come_first() {
+        let (current, desired) = sync_case();
+        let events = TimeTaskDiff::new(&current, &desired).events();
+        assert_eq!(
+            events,
+            [
+                TimerEvent::RemoveTask("move".into()),
+                TimerEvent::RemoveTask("drop".into()),
+                TimerEvent::AddTask(desired[1].clone()),
+                TimerEvent::AddTask(desired[2].clone()),
+            ]
+        );
+
+        let mut tasks = current;
+        for event in &events {
+            apply(&mut tasks, event);
+        }
+        tasks.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut desired = desired;
+        desired.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(tasks, desired);
+    }
+
+    #[test]
+    fn inverse_of_each_event() {
+        let (current, _) = sync_case();
+        let added = once("new", LedCommand::Reset, at(9, 12, 0));
+        assert_eq!(
+            TimerEvent::AddTask(added).inverse(&current),
+            Some(TimerEvent::RemoveTask("new".into()))
+        );
+        assert_eq!(
+            TimerEvent::RemoveTask("drop".into()).inverse(&current),
+            Some(TimerEvent::AddTask(current[2].clone()))
+        );
+        assert_eq!(
+            TimerEvent::RemoveTask("unknown".into()).inverse(&current),
+            None
+        );
+    }
+
+    #[test]
+    fn inverses_roll_back_a_partial_sync() {
+        let (current, desired) = sync_case();
+        let events = TimeTaskDiff::new(&current, &desired).events();
+        // the third event fails, the two applied before it are undone in reverse
+        // against the list read before the sync, as `sync_tasks` does
+        let mut tasks = current.clone();
+        let applied = &events[..2];
+        for event in applied {
+            apply(&mut tasks, event);
+        }
+        for undo in applied
+            .iter()
+            .rev()
+            .filter_map(|event| event.inverse(&current))
+        {
+            apply(&mut tasks, &undo);
+        }
+        tasks.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut current = current;
+        current.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(tasks, current);
+    }
+}
@@ -9,7 +9,12 @@ use msg::{NotifyMessage, ReadMessage};
 use rand::random;
 use serde::{Deserialize, Serialize};
 
-use std::{fmt::Debug, marker::PhantomData};
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 pub mod meta_date;
 pub mod msg;
@@ -29,6 +34,8 @@ where
 {
     peripheral: Peripheral,
     pub characteristic: Characteristic,
+    /// Size and duration of the last completed transfer, in either direction.
+    last_transfer: Arc<Mutex<Option<(usize, Duration)>>>,
     _phantom: PhantomData<T>,
 }
 
@@ -43,11 +50,25 @@ where
         Ok(Self {
             peripheral,
             characteristic,
+            last_transfer: Arc::default(),
             _phantom: PhantomData,
         })
     }
 
+    /// How long a transfer of `size` bytes should take, scaled from the last one.
+    pub fn estimate(&self, size: usize) -> Option<Duration> {
+        let (last_size, took) = (*self.last_transfer.lock().ok()?)?;
+        Some(took.mul_f64(size as f64 / last_size.max(1) as f64))
+    }
+
+    fn record(&self, size: usize, started: Instant) {
+        if let Ok(mut last) = self.last_transfer.lock() {
+            *last = Some((size, started.elapsed()));
+        }
+    }
+
     pub async fn read_value(&self) -> Result<T> {
+        let started = Instant::now();
         self.peripheral.subscribe(&self.characteristic).await?;
         self.peripheral
             .write(
@@ -86,6 +107,7 @@ where
                                     )
                                     .await?;
 
+                                self.record(value.len(), started);
                                 return Ok(serde_json::from_slice(&value)?);
                             }
                         } else {
@@ -99,6 +121,7 @@ where
     }
 
     pub async fn write_value(&self, value: &T) -> Result<()> {
+        let started = Instant::now();
         let id = random::<u32>();
         let data = serde_json::to_vec(value)?;
         let total_size = data.len();
@@ -166,6 +189,7 @@ where
                         }
                     }
                     NotifyMessage::WriteFinish => {
+                        self.record(total_size, started);
                         return Ok(());
                     }
                     _ => {}
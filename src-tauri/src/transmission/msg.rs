@@ -10,6 +10,8 @@ pub enum ReadMessage {
     ReadFinish,
     StartWrite(MetaData),
     Write(ChunkMetaData),
+    Preview([u8; 3]),
+    PreviewEnd,
 }
 
 impl DataFromBytes for ReadMessage {
@@ -29,6 +31,11 @@ impl DataFromBytes for ReadMessage {
                 let (chunk_meta_date, bytes) = ChunkMetaData::from_data(&bytes[1..]);
                 (ReadMessage::Write(chunk_meta_date), bytes)
             }
+            5 => (
+                ReadMessage::Preview([bytes[1], bytes[2], bytes[3]]),
+                &bytes[4..],
+            ),
+            6 => (ReadMessage::PreviewEnd, &bytes[1..]),
             _ => {
                 unreachable!()
             }
@@ -53,6 +60,12 @@ impl DataFromBytes for ReadMessage {
                 bytes.extend(chunk_meta_date.bytes());
                 bytes
             }
+            ReadMessage::Preview(rgb) => {
+                let mut bytes = vec![5];
+                bytes.extend(rgb);
+                bytes
+            }
+            ReadMessage::PreviewEnd => vec![6],
         }
     }
 }
@@ -0,0 +1,107 @@
+use anyhow::anyhow;
+use btleplug::platform::PeripheralId;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use tauri::{AppHandle, State};
+#[cfg(dev)]
+use tracing::info;
+
+use crate::error::Result;
+use crate::group::{fan_out, members, write_synced, DeviceResult, START_LEAD};
+use crate::preview::Preview;
+use crate::queue::WriteStatus;
+use crate::scene::{Scene, SceneKind};
+use crate::settings::Settings;
+use crate::state::{get_led, AppState};
+
+/// A gradient travelling across the lamps of a group in their order.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Wave {
+    pub scene: Scene,
+    /// Seconds each lamp lags behind the one before it, negative runs the other way.
+    pub phase: f64,
+    /// Renders the frames on the host instead of writing the scene to the lamps.
+    #[serde(default)]
+    pub stream: bool,
+}
+
+impl Wave {
+    fn validate(&self) -> Result<()> {
+        if !matches!(self.scene.kind, SceneKind::Gradient { .. }) {
+            return Err(anyhow!("wave needs a gradient scene").into());
+        }
+        if !self.phase.is_finite() {
+            return Err(anyhow!("wave phase {} is not a number", self.phase).into());
+        }
+        Ok(self.scene.validate()?)
+    }
+
+    /// The scene of every lamp, each starting `phase` later than the one before.
+    ///
+    /// The starts are moved back so the latest one falls on `start_at`, a lamp that gets
+    /// its scene late joins the wave where it already is. Lamps without the `startAt`
+    /// feature cannot, for them the wave is only kept by `stream`.
+    fn scenes(
+        &self,
+        devices: Vec<PeripheralId>,
+        start_at: DateTime<Utc>,
+    ) -> Vec<(PeripheralId, Scene)> {
+        let shift = |index: usize| {
+            TimeDelta::milliseconds((index as f64 * self.phase * 1000.0).round() as i64)
+        };
+        let last = shift(devices.len().saturating_sub(1)).max(TimeDelta::zero());
+        devices
+            .into_iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let scene = Scene {
+                    start_at: Some(start_at - last + shift(index)),
+                    ..self.scene.clone()
+                };
+                (id, scene)
+            })
+            .collect()
+    }
+}
+
+/// Plays a wave across the group, lamps are shifted by their place in the group
+/// so retrying some of them with `only` keeps them in the wave.
+#[tauri::command]
+pub async fn group_wave(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    wave: Wave,
+    only: Option<Vec<PeripheralId>>,
+) -> Result<Vec<DeviceResult<WriteStatus>>> {
+    #[cfg(dev)]
+    info!("group_wave name: {name} value: {wave:#?}");
+    wave.validate()?;
+    let gamma = Settings::load(&app)?.gamma;
+    let start_at = wave.scene.start_at.unwrap_or(Utc::now() + START_LEAD);
+    let mut scenes = wave.scenes(members(&app, &name, None)?, start_at);
+    if let Some(only) = only {
+        scenes.retain(|(id, _)| only.contains(id));
+    }
+    if !wave.stream {
+        return Ok(write_synced(&state, gamma, scenes).await);
+    }
+
+    // frames follow the host clock, no clock offsets to make up for
+    let state = &*state;
+    let scenes = &scenes;
+    let ids = scenes.iter().map(|(id, _)| id.clone()).collect();
+    Ok(fan_out(ids, |id| async move {
+        let led = get_led(state, &id).await?;
+        let scene = scenes
+            .iter()
+            .find(|(item, _)| item == &id)
+            .map(|(_, scene)| scene.clone())
+            .ok_or(anyhow!("Led not found"))?;
+        let preview = Preview::play(led, scene, gamma)?;
+        state.lock().await?.previews.insert(id, preview);
+        Ok(WriteStatus::Applied)
+    })
+    .await)
+}
@@ -1,17 +1,88 @@
 import { Channel, invoke } from "@tauri-apps/api/core";
 import { EventCallback, listen } from "@tauri-apps/api/event";
-import { Device, Scene, TimerTask } from "./interface";
+import {
+  AdapterInfo,
+  AdapterState,
+  CacheInfo,
+  ColorInput,
+  ConnectionEvent,
+  Device,
+  DeviceResult,
+  Execution,
+  Features,
+  Group,
+  Health,
+  ImportReport,
+  Playlist,
+  ScanEvent,
+  PlaylistState,
+  ReconnectPolicy,
+  RegisteredDevice,
+  Scene,
+  StoredScene,
+  Schedule,
+  ScheduleRule,
+  Settings,
+  SolarTask,
+  TimeTaskIssue,
+  TimeTaskSync,
+  TimerTask,
+  Wave,
+  WriteStatus,
+} from "./interface";
 import { TimeTask } from "../stores/useTimeTaskStore";
+import { ScenesConfig } from "../stores/useScenesStore";
 
 export const init = () => {
   return invoke<string>("init");
 };
 
-export function startScan(cb?: (data: Device[]) => void) {
-  const channel = new Channel<Device[]>();
-  channel.onmessage = cb || (() => {});
+export function listAdapters() {
+  return invoke<AdapterInfo[]>("list_adapters");
+}
+
+export function selectAdapter(name: string | null) {
+  return invoke<void>("select_adapter", {
+    name,
+  });
+}
+
+export function onAdapterChanged(cb: EventCallback<string | null>) {
+  return listen<string | null>("adapter-changed", cb);
+}
+
+export function getAdapterState() {
+  return invoke<AdapterState>("get_adapter_state");
+}
+
+/**
+ * 适配器拔出或蓝牙开关变化时触发，关闭期间扫描和重连会暂停
+ */
+export function onAdapterState(cb: EventCallback<AdapterState>) {
+  return listen<AdapterState>("adapter-state", cb);
+}
+
+/**
+ * 开始扫描，后端只发送增量事件，这里合并成完整的设备列表回调
+ * @param duration 扫描时长（秒），默认 30 秒后自动停止，蓝牙关闭期间暂停计时
+ */
+export function startScan(
+  cb?: (data: Device[], event: ScanEvent) => void,
+  duration?: number
+) {
+  const devices = new Map<string, Device>();
+  const channel = new Channel<ScanEvent>();
+  channel.onmessage = (event) => {
+    if (event.type === "added" || event.type === "updated") {
+      devices.set(event.data.id, event.data);
+    } else if (event.type === "lost") {
+      devices.delete(event.data);
+    }
+    cb?.(Array.from(devices.values()), event);
+  };
   return invoke<void>("start_scan", {
     channel,
+    duration,
   });
 }
 
@@ -35,22 +106,228 @@ export function disconnectDevice(id: string) {
   });
 }
 
+export function onConnection(id: string, cb: EventCallback<ConnectionEvent>) {
+  return listen<ConnectionEvent>(`connection-${id}`, cb);
+}
+
+export function getHealth() {
+  return invoke<Health[]>("get_health");
+}
+
+export function onHealth(id: string, cb: EventCallback<Health>) {
+  return listen<Health>(`health-${id}`, cb);
+}
+
+export function getReconnectPolicies() {
+  return invoke<ReconnectPolicy[]>("get_reconnect_policies");
+}
+
+export function setReconnectPolicy(policy: ReconnectPolicy) {
+  return invoke<void>("set_reconnect_policy", {
+    policy,
+  });
+}
+
+export function getRegistry() {
+  return invoke<RegisteredDevice[]>("get_registry");
+}
+
+export function registerDevice(id: string, name?: string | null) {
+  return invoke<void>("register_device", {
+    id,
+    name,
+  });
+}
+
+export function updateDevice(device: RegisteredDevice) {
+  return invoke<void>("update_device", {
+    device,
+  });
+}
+
+export function unregisterDevice(id: string) {
+  return invoke<void>("unregister_device", {
+    id,
+  });
+}
+
+export function exportRegistry() {
+  return invoke<boolean>("export_registry");
+}
+
 export function control(id: string, command: "open" | "close" | "reset") {
-  return invoke<void>("control", {
+  return invoke<WriteStatus>("control", {
     id,
     command,
   });
 }
 
 export function setScene(id: string, scene: Scene) {
-  return invoke<void>("set_scene", {
+  return invoke<WriteStatus>("set_scene", {
+    id,
+    scene,
+  });
+}
+
+export function previewColor(id: string, color: ColorInput) {
+  return invoke<void>("preview_color", {
+    id,
+    color,
+  });
+}
+
+export function playScene(id: string, scene: Scene) {
+  return invoke<void>("play_scene", {
     id,
     scene,
   });
 }
 
-export function getScene(id: string) {
-  return invoke<Scene>("get_scene", {
+export function renderScene(scene: Scene, sampleRate: number, seconds?: number) {
+  return invoke<string[]>("render_scene", {
+    scene,
+    sampleRate,
+    seconds,
+  });
+}
+
+export function stopPreview(id: string) {
+  return invoke<void>("stop_preview", {
+    id,
+  });
+}
+
+export function playPlaylist(id: string, playlist: Playlist) {
+  return invoke<void>("play_playlist", {
+    id,
+    playlist,
+  });
+}
+
+export function stopPlaylist(id: string) {
+  return invoke<void>("stop_playlist", {
+    id,
+  });
+}
+
+export function getPlaylistState(id: string) {
+  return invoke<PlaylistState | null>("get_playlist_state", {
+    id,
+  });
+}
+
+export function onPlaylistState(
+  id: string,
+  cb: EventCallback<PlaylistState | null>
+) {
+  return listen<PlaylistState | null>(`playlist-${id}`, cb);
+}
+
+export function listPlaylists() {
+  return invoke<Playlist[]>("list_playlists");
+}
+
+export function savePlaylist(playlist: Playlist) {
+  return invoke<void>("save_playlist", {
+    playlist,
+  });
+}
+
+export function deletePlaylist(name: string) {
+  return invoke<void>("delete_playlist", {
+    name,
+  });
+}
+
+export function listGroups() {
+  return invoke<Group[]>("list_groups");
+}
+
+export function saveGroup(group: Group) {
+  return invoke<void>("save_group", {
+    group,
+  });
+}
+
+export function deleteGroup(name: string) {
+  return invoke<void>("delete_group", {
+    name,
+  });
+}
+
+/**
+ * 分组命令同时发送给所有成员，返回每个设备的结果
+ * @param only 只发送给这些设备，用于重试失败的设备
+ */
+export function groupControl(
+  name: string,
+  command: "open" | "close" | "reset",
+  only?: string[]
+) {
+  return invoke<DeviceResult<WriteStatus>[]>("group_control", {
+    name,
+    command,
+    only,
+  });
+}
+
+/**
+ * 所有成员使用同一个开始时间，并按各自的时钟偏差校正，渐变同步播放
+ * 场景没有 startAt 时由后端安排在几秒后开始
+ */
+export function groupSetScene(name: string, scene: Scene, only?: string[]) {
+  return invoke<DeviceResult<WriteStatus>[]>("group_set_scene", {
+    name,
+    scene,
+    only,
+  });
+}
+
+export function groupSetTimer(
+  name: string,
+  timerEvent: TimerTask,
+  only?: string[]
+) {
+  return invoke<DeviceResult<null>[]>("group_set_timer", {
+    name,
+    timerEvent,
+    only,
+  });
+}
+
+/**
+ * 在分组中按顺序播放波浪效果，重试部分设备时仍保持各自的相位
+ */
+export function groupWave(name: string, wave: Wave, only?: string[]) {
+  return invoke<DeviceResult<WriteStatus>[]>("group_wave", {
+    name,
+    wave,
+    only,
+  });
+}
+
+export function sceneFromImage(count: number, duration: number) {
+  return invoke<StoredScene | null>("scene_from_image", {
+    count,
+    duration,
+  });
+}
+
+export function getScene(id: string, force?: boolean) {
+  return invoke<StoredScene>("get_scene", {
+    id,
+    force,
+  });
+}
+
+export function getFeatures(id: string) {
+  return invoke<Features>("get_features", {
+    id,
+  });
+}
+
+export function getCacheInfo(id: string) {
+  return invoke<CacheInfo>("get_cache_info", {
     id,
   });
 }
@@ -65,8 +342,8 @@ export function onLedState(id: string, cb: EventCallback<"opened" | "closed">) {
   return listen<"opened" | "closed">(`state-${id}`, cb);
 }
 
-export function onLedScene(id: string, cb: EventCallback<Scene>) {
-  return listen<Scene>(`scene-${id}`, cb);
+export function onLedScene(id: string, cb: EventCallback<StoredScene>) {
+  return listen<StoredScene>(`scene-${id}`, cb);
 }
 
 export function onLedTimeTasks(id: string, cb: EventCallback<TimeTask[]>) {
@@ -80,8 +357,135 @@ export function setTimer(id: string, timerEvent: TimerTask) {
   });
 }
 
-export function getTimeTasks(id: string) {
+export function validateTimeTasks(tasks: TimeTask[]) {
+  return invoke<TimeTaskIssue[]>("validate_time_tasks", {
+    tasks,
+  });
+}
+
+export function syncTimeTasks(id: string, desired: TimeTask[]) {
+  return invoke<TimeTaskSync>("sync_time_tasks", {
+    id,
+    desired,
+  });
+}
+
+export function getTimeTasks(id: string, force?: boolean) {
   return invoke<TimeTask[]>("get_time_tasks", {
     id,
+    force,
   });
 }
+
+export function getSettings() {
+  return invoke<Settings>("get_settings");
+}
+
+// 只更新传入的字段，其余设置保持不变
+export function setSettings(settings: Partial<Settings>) {
+  return invoke<void>("set_settings", {
+    settings,
+  });
+}
+
+export function getSolarTasks() {
+  return invoke<SolarTask[]>("get_solar_tasks");
+}
+
+export function addSolarTask(task: SolarTask) {
+  return invoke<void>("add_solar_task", {
+    task,
+  });
+}
+
+export function removeSolarTask(id: string, name: string) {
+  return invoke<void>("remove_solar_task", {
+    id,
+    name,
+  });
+}
+
+export function getSchedule() {
+  return invoke<Schedule>("get_schedule");
+}
+
+export function addScheduleRule(rule: ScheduleRule) {
+  return invoke<void>("add_schedule_rule", {
+    rule,
+  });
+}
+
+export function updateScheduleRule(name: string, rule: ScheduleRule) {
+  return invoke<void>("update_schedule_rule", {
+    name,
+    rule,
+  });
+}
+
+export function removeScheduleRule(name: string) {
+  return invoke<void>("remove_schedule_rule", {
+    name,
+  });
+}
+
+export function setHolidays(holidays: string[]) {
+  return invoke<void>("set_holidays", {
+    holidays,
+  });
+}
+
+export function getScheduleHistory() {
+  return invoke<Execution[]>("get_schedule_history");
+}
+
+export function listScenes() {
+  return invoke<ScenesConfig[]>("list_scenes");
+}
+
+export function createScene(scene: ScenesConfig) {
+  return invoke<void>("create_scene", {
+    scene,
+  });
+}
+
+export function updateScene(name: string, scene: ScenesConfig) {
+  return invoke<void>("update_scene", {
+    name,
+    scene,
+  });
+}
+
+export function renameScene(name: string, newName: string) {
+  return invoke<void>("rename_scene", {
+    name,
+    newName,
+  });
+}
+
+export function deleteScene(name: string) {
+  return invoke<void>("delete_scene", {
+    name,
+  });
+}
+
+export function importLegacyScenes(scenes: ScenesConfig[]) {
+  return invoke<number>("import_legacy_scenes", {
+    scenes,
+  });
+}
+
+export function exportScenes(
+  names: string[],
+  author?: string,
+  description?: string
+) {
+  return invoke<boolean>("export_scenes", {
+    names,
+    author,
+    description,
+  });
+}
+
+export function importScenes() {
+  return invoke<ImportReport | null>("import_scenes");
+}
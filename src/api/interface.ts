@@ -1,26 +1,50 @@
-export type Scene = {
+// 发给后端的颜色可以是任意 ColorInput，后端返回的场景颜色总是十六进制字符串
+export type Scene<C = ColorInput> = {
   name: string;
   autoOn: boolean;
+  // 第一个颜色开始的时间（ISO 字符串），相同开始时间的灯同步播放
+  startAt?: string | null;
 } & (
-  | { type: "solid"; color: string }
+  | { type: "solid"; color: C }
   | {
       type: "gradient";
-      colors: ColorDuration[];
+      colors: ColorDuration<C>[];
       linear: boolean;
     }
 );
 
-export type ColorDuration = {
-  color: string;
+// 后端接受的颜色格式：十六进制、CSS 颜色名、RGB、HSV 或色温
+export type ColorInput =
+  | string
+  | [number, number, number]
+  | { h: number; s: number; v: number }
+  | { kelvin: number };
+
+// 从后端读取的场景
+export type StoredScene = Scene<string>;
+
+export type Curve = "linear" | "easeIn" | "easeOut" | "easeInOut" | "step";
+
+export type ColorDuration<C = ColorInput> = {
+  color: C;
   duration: number;
+  curve?: Curve;
+  hold?: number;
 };
 
 export type Device = {
   id: string;
   address: string;
   local_name: string;
+  rssi?: number | null;
+  tx_power_level?: number | null;
 };
 
+export type ScanEvent =
+  | { type: "added" | "updated"; data: Device }
+  | { type: "lost"; data: string }
+  | { type: "stopped" | "paused" | "resumed" };
+
 export type RemoveTask = {
   type: "removeTask";
   data: string;
@@ -49,3 +73,181 @@ export type AddTask = {
 };
 
 export type TimerTask = RemoveTask | AddTask;
+
+export type TimeTaskSync = {
+  added: string[];
+  removed: string[];
+  updated: string[];
+  unchanged: string[];
+};
+
+export type TimeTaskIssue =
+  | { kind: "duplicateName"; name: string }
+  | { kind: "conflict"; first: string; second: string }
+  | { kind: "pastOnce"; name: string };
+
+export type Location = {
+  latitude: number;
+  longitude: number;
+};
+
+export type Settings = {
+  location?: Location | null;
+  gamma?: number;
+  adapter?: string | null;
+};
+
+export type SolarTask = {
+  id: string;
+  name: string;
+  operation: "open" | "close" | "reset";
+  event: "sunrise" | "sunset";
+  offset: number;
+  synced?: string | null;
+  // 上次写入设备失败的原因
+  error?: string | null;
+};
+
+export type Recurrence =
+  | { kind: "once"; at: string }
+  | { kind: "interval"; start: string; interval: number; time: string }
+  | { kind: "weekdays"; days: number[]; time: string; skipHolidays?: boolean };
+
+export type ScheduleAction =
+  | { type: "control"; data: "open" | "close" | "reset" }
+  | { type: "scene"; data: Scene };
+
+export type ScheduleRule = {
+  name: string;
+  devices: string[];
+  recurrence: Recurrence;
+  action: ScheduleAction;
+  enabled?: boolean;
+  catchUp?: boolean;
+  lastRun?: string | null;
+};
+
+export type Schedule = {
+  rules: ScheduleRule[];
+  holidays: string[];
+};
+
+export type Execution = {
+  rule: string;
+  id: string;
+  scheduled: string;
+  executed: string;
+  catchUp: boolean;
+  error: string | null;
+};
+
+export type ImportReport = {
+  author: string | null;
+  description: string | null;
+  imported: string[];
+  skipped: { name: string; reason: string }[];
+};
+
+export type PlaylistItem = {
+  scene: Scene;
+  dwell: number;
+};
+
+export type Playlist = {
+  name: string;
+  items: PlaylistItem[];
+  loop?: boolean;
+  shuffle?: boolean;
+};
+
+export type PlaylistState = {
+  name: string;
+  index: number;
+  scene: string;
+  nextChange: string | null;
+  uploaded: boolean;
+};
+
+// 固件支持的扩展功能，旧固件全部为 false
+export type Features = {
+  preview: boolean;
+  curves: boolean;
+  startAt: boolean;
+};
+
+export type CacheInfo = {
+  scene: string | null;
+  timeTasks: string | null;
+};
+
+export type AdapterInfo = {
+  name: string;
+  selected: boolean;
+  preferred: boolean;
+};
+
+export type RadioState = "on" | "off" | "unknown";
+
+export type AdapterState = {
+  name: string | null;
+  radio: RadioState;
+};
+
+export type ReconnectPolicy = {
+  id: string;
+  enabled?: boolean;
+  maxAttempts?: number;
+};
+
+export type ConnectionEvent =
+  | {
+      status: "reconnecting";
+      attempt: number;
+      maxAttempts: number;
+      delay: number;
+    }
+  | { status: "reconnected" }
+  | { status: "paused" }
+  | { status: "failed" };
+
+export type Health = {
+  id: string;
+  status: "healthy" | "degraded" | "lost";
+  latency: number | null;
+  failures: number;
+  lastSeen: string | null;
+  checkedAt: string;
+};
+
+export type RegisteredDevice = {
+  id: string;
+  name?: string | null;
+  alias?: string | null;
+  room?: string | null;
+  lastSeen?: string | null;
+  autoConnect?: boolean;
+};
+
+// 连续的写入只执行最新的一次，被替换的请求返回 superseded
+export type WriteStatus = "applied" | "superseded";
+
+// 成员按摆放顺序排列
+export type Group = {
+  name: string;
+  devices: string[];
+};
+
+// 分组命令中每个设备的结果，error 不为 null 时表示失败
+export type DeviceResult<T> = {
+  id: string;
+  value: T | null;
+  error: string | null;
+};
+
+// 沿分组顺序移动的渐变，phase 为相邻两灯的延迟（秒），负数反向
+export type Wave = {
+  scene: Scene;
+  phase: number;
+  // 由电脑逐帧发送，不写入灯
+  stream?: boolean;
+};
@@ -2,12 +2,14 @@ import { Divider } from "@nextui-org/divider";
 import { NextUIProvider } from "@nextui-org/system";
 import { useAsyncEffect } from "ahooks";
 import { Outlet } from "react-router-dom";
-import { init } from "../api";
+import { init, registerDevice } from "../api";
 import { ThemeProvider, useTheme } from "../hooks/useTheme";
 import { Slider } from "./Slider";
 import { App, ConfigProvider, theme as AntdTheme } from "antd";
 import { useUpdater } from "../hooks/useUpdater";
 import { useState } from "react";
+import { useScenesStore } from "../stores/useScenesStore";
+import { useDeviceStore } from "../stores/useDeviceStore";
 
 export function Layout() {
   return (
@@ -41,8 +43,22 @@ function AppLayout() {
 function MyApp() {
   const { message } = App.useApp();
   const [initialized, setInitialized] = useState(false);
+  const loadScenes = useScenesStore((store) => store.loadScenes);
   useAsyncEffect(async () => {
     try {
+      await loadScenes();
+    } catch (error) {
+      message.error(`加载场景失败 ${error}`);
+    }
+  }, []);
+  useAsyncEffect(async () => {
+    try {
+      // 已添加的设备登记到后端，init 会在后台自动连接
+      await Promise.all(
+        useDeviceStore
+          .getState()
+          .devices.map((device) => registerDevice(device.id, device.local_name))
+      );
       const res = await init();
       console.log(res);
       setInitialized(true);
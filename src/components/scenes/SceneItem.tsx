@@ -1,10 +1,10 @@
 import { useMemo } from "react";
-import { Scene } from "../../api/interface";
+import { StoredScene } from "../../api/interface";
 import chroma from "chroma-js";
 import { Skeleton } from "@nextui-org/skeleton";
 
 type SceneItemProps = {
-  scene?: Scene;
+  scene?: StoredScene;
 };
 
 export const SceneItem = ({ scene }: SceneItemProps) => {
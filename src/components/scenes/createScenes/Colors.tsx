@@ -8,10 +8,11 @@ import { ScrollShadow } from "@nextui-org/scroll-shadow";
 import { ColorDuration } from "../../../api/interface";
 
 export const Colors = (props: {
-  value?: ColorDuration[];
-  onChange?: (value: ColorDuration[]) => void;
+  value?: ColorDuration<string>[];
+  onChange?: (value: ColorDuration<string>[]) => void;
 }) => {
-  const [value, setValue] = useControllableValue<ColorDuration[]>(props);
+  const [value, setValue] =
+    useControllableValue<ColorDuration<string>[]>(props);
 
   return (
     <div className="flex flex-col gap-2">
@@ -25,8 +25,8 @@ import chroma from "chroma-js";
 import { Colors } from "./Colors";
 
 export type CreateScenesProps = {
-  onCreate?: (config: ScenesConfig) => void;
-  onEdit?: (currentName: string, config: ScenesConfig) => void;
+  onCreate?: (config: ScenesConfig) => Promise<void> | void;
+  onEdit?: (currentName: string, config: ScenesConfig) => Promise<void> | void;
 };
 
 export type CreateScenesRef = {
@@ -245,10 +245,15 @@ const CreateScenes = (
             onClick={async () => {
               const validated = await trigger();
               if (validated) {
-                if (isEdit) {
-                  props.onEdit?.(currentName.current, data as any);
-                } else {
-                  props.onCreate?.(data as any);
+                try {
+                  if (isEdit) {
+                    await props.onEdit?.(currentName.current, data as any);
+                  } else {
+                    await props.onCreate?.(data as any);
+                  }
+                } catch {
+                  // 保存失败时保留弹窗，方便修改后重试
+                  return;
                 }
                 setIsOpen(false);
                 setIsEdit(false);
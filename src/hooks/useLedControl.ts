@@ -1,6 +1,5 @@
 import { UnlistenFn } from "@tauri-apps/api/event";
 import { App } from "antd";
-import chroma from "chroma-js";
 import { useEffect, useState } from "react";
 import {
   connectDevice,
@@ -15,7 +14,7 @@ import {
   setScene,
   setTimer,
 } from "../api";
-import { Device, Scene, TimerTask } from "../api/interface";
+import { Device, Scene, StoredScene, TimerTask } from "../api/interface";
 import { TimeTask } from "../stores/useTimeTaskStore";
 
 export const useLedControl = (device?: string | Device) => {
@@ -23,7 +22,7 @@ export const useLedControl = (device?: string | Device) => {
   const [ledState, setLedState] = useState<"opened" | "closed">("closed");
   const [isCollected, setIsCollected] = useState(false);
   const [isCollecting, setIsCollecting] = useState(false);
-  const [ledScene, setLedScene] = useState<Scene>();
+  const [ledScene, setLedScene] = useState<StoredScene>();
   const [ledDevice, setLedDevice] = useState<Device>();
   const [timeTasks, setTimeTasks] = useState<TimeTask[]>([]);
 
@@ -161,20 +160,11 @@ export const useLedControl = (device?: string | Device) => {
       return message.error(`设备未连接`);
     }
     try {
-      const newScene = structuredClone(scene);
-      if (newScene) {
-        if (newScene.type === "solid") {
-          newScene.color = chroma(newScene.color).rgb() as any;
-        } else {
-          newScene.colors = newScene.colors.map(
-            (item) => ({ ...item, color: chroma(item.color).rgb() } as any)
-          );
-        }
-        await setScene(ledDevice.id, newScene);
-        message.success(
-          `设备 (${ledDevice.local_name || ledDevice.id}) 设置场景成功`
-        );
-      }
+      // 后端接受任意 ColorInput，无需在这里转换颜色
+      await setScene(ledDevice.id, scene);
+      message.success(
+        `设备 (${ledDevice.local_name || ledDevice.id}) 设置场景成功`
+      );
     } catch (error) {
       message.error(
         `设备 (${ledDevice.local_name || ledDevice.id}) 设置场景失败`
@@ -32,7 +32,7 @@ export const Devices = () => {
         // setScanning(false);
       }
       setRefreshing(false);
-    })
+    }, 5)
       .then(async () => {
         setData(await getDevices());
       })
@@ -42,9 +42,6 @@ export const Devices = () => {
       .finally(() => {
         setRefreshing(false);
       });
-    setTimeout(() => {
-      stopScan();
-    }, 5000);
     return () => {
       stopScan();
     };
@@ -10,8 +10,10 @@ import { useScenesStore } from "../../stores/useScenesStore";
 import { Scene } from "../../components/scenes/Scene";
 import { ScrollShadow } from "@nextui-org/scroll-shadow";
 import { Input } from "@nextui-org/input";
+import { App } from "antd";
 
 export const Scenes = () => {
+  const { message } = App.useApp();
   const createScenesRef = useRef<CreateScenesRef>(null);
   const [search, setSearch] = useState("");
   const [scenes, addScene, removeScene, updateScene] = useScenesStore(
@@ -78,8 +80,12 @@ export const Scenes = () => {
                 <Scene
                   key={item.name}
                   data={item}
-                  onDelete={(config) => {
-                    removeScene(config.name);
+                  onDelete={async (config) => {
+                    try {
+                      await removeScene(config.name);
+                    } catch (error) {
+                      message.error(`删除场景失败 ${error}`);
+                    }
                   }}
                   onClick={(config) => {
                     createScenesRef.current?.edit(config);
@@ -107,10 +113,22 @@ export const Scenes = () => {
       </Tooltip>
       <CreateScenes
         ref={createScenesRef}
-        onCreate={(scene) => {
-          addScene(scene);
+        onCreate={async (scene) => {
+          try {
+            await addScene(scene);
+          } catch (error) {
+            message.error(`创建场景失败 ${error}`);
+            throw error;
+          }
+        }}
+        onEdit={async (name, scene) => {
+          try {
+            await updateScene(name, scene);
+          } catch (error) {
+            message.error(`保存场景失败 ${error}`);
+            throw error;
+          }
         }}
-        onEdit={updateScene}
       />
     </div>
   );
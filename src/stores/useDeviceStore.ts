@@ -1,4 +1,5 @@
 import { Device } from "./../api/interface";
+import { registerDevice, unregisterDevice } from "../api";
 import { create } from "zustand";
 import { persist } from "zustand/middleware";
 import { immer } from "zustand/middleware/immer";
@@ -22,11 +23,13 @@ export const useDeviceStore = create(
             state.devices.push(device);
           }
         });
+        registerDevice(device.id, device.local_name).catch(console.error);
       },
       removeDevice: (id) => {
         set((state) => {
           state.devices = state.devices.filter((device) => device.id !== id);
         });
+        unregisterDevice(id).catch(console.error);
       },
     })),
     {
@@ -1,9 +1,15 @@
 import { create } from "zustand";
-import { persist } from "zustand/middleware";
 import { immer } from "zustand/middleware/immer";
-import { Scene } from "../api/interface";
+import { StoredScene } from "../api/interface";
+import {
+  createScene,
+  deleteScene,
+  importLegacyScenes,
+  listScenes,
+  updateScene,
+} from "../api";
 
-export type ScenesConfig = Scene & {
+export type ScenesConfig = StoredScene & {
   description?: string;
   isBuiltin?: boolean;
 };
@@ -13,56 +19,56 @@ type SceneState = {
 };
 
 type SceneActions = {
-  addScene: (scene: ScenesConfig) => void;
-  removeScene: (name: string) => void;
-  updateScene: (name: string, scene: ScenesConfig) => void;
+  loadScenes: () => Promise<void>;
+  addScene: (scene: ScenesConfig) => Promise<void>;
+  removeScene: (name: string) => Promise<void>;
+  updateScene: (name: string, scene: ScenesConfig) => Promise<void>;
 };
 
+// 旧版本场景保存在 webview 的 localStorage 中，首次加载时导入到后端
+async function importLocalScenes() {
+  const data = localStorage.getItem("scenes");
+  if (!data) return;
+  try {
+    const scenes = JSON.parse(data)?.state?.scenes;
+    if (Array.isArray(scenes)) {
+      await importLegacyScenes(scenes);
+    }
+  } catch (error) {
+    console.error(error);
+  }
+}
+
 export const useScenesStore = create(
-  persist(
-    immer<SceneState & SceneActions>((set) => ({
-      scenes: [
-        {
-          type: "solid",
-          name: "default",
-          autoOn: false,
-          color: "#ffffff",
-          description: "默认场景",
-          isBuiltin: true,
-        },
-        {
-          autoOn: false,
-          color: "#f5a524",
-          colors: [
-            { color: "#FA8C16", duration: 2 },
-            { color: "#000000", duration: 2 },
-          ],
-          description: "呼吸灯",
-          linear: true,
-          name: "Breathe",
-          type: "gradient",
-          isBuiltin: true,
-        },
-      ],
-      addScene: (scene) => {
-        set((state) => {
-          state.scenes.push(scene);
-        });
-      },
-      removeScene: (name) => {
-        set((state) => {
-          state.scenes = state.scenes.filter((scene) => scene.name !== name);
-        });
-      },
-      updateScene: (name, scene) => {
-        set((state) => {
-          const index = state.scenes.findIndex((scene) => scene.name === name);
-          if (index !== -1) {
-            state.scenes[index] = scene;
-          }
-        });
-      },
-    })),
-    { name: "scenes" }
-  )
+  immer<SceneState & SceneActions>((set) => ({
+    scenes: [],
+    loadScenes: async () => {
+      await importLocalScenes();
+      const scenes = await listScenes();
+      set((state) => {
+        state.scenes = scenes;
+      });
+    },
+    addScene: async (scene) => {
+      await createScene(scene);
+      set((state) => {
+        state.scenes.push(scene);
+      });
+    },
+    removeScene: async (name) => {
+      await deleteScene(name);
+      set((state) => {
+        state.scenes = state.scenes.filter((scene) => scene.name !== name);
+      });
+    },
+    updateScene: async (name, scene) => {
+      await updateScene(name, scene);
+      set((state) => {
+        const index = state.scenes.findIndex((scene) => scene.name === name);
+        if (index !== -1) {
+          state.scenes[index] = scene;
+        }
+      });
+    },
+  }))
 );